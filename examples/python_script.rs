@@ -30,11 +30,11 @@ fn main() -> sombra::Result<()> {
     let mut stream = TcpStream::connect("127.0.0.1:30222")?;
     println!("Sending \"{}\" to Background TCP Server...", msg);
     // Sending a message to the backgrounding TCP Server
-    stream.write(msg.as_bytes())?;
+    stream.write_all(msg.as_bytes())?;
     println!("Message sent with success.");
 
     // Wait the response of backgrounding TCP Server
-    stream.read(&mut buffer)?;
+    let _ = stream.read(&mut buffer)?;
     // Cast 'buffer' to Vector
     let mut buffer = buffer.to_vec();
     // Retain only non empty bytes
@@ -0,0 +1,82 @@
+use std::path::Path;
+
+/// Service manager driven by sombra on Linux, picked by `Backend::detect`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// systemd, through systemctl
+    Systemd,
+    /// OpenRC, through rc-service and init scripts run by supervise-daemon
+    OpenRC,
+    /// SysV init, through LSB init scripts run by start-stop-daemon
+    SysV,
+    /// runit, through service directories and sv
+    Runit,
+    /// s6, through service directories, s6-svc and s6-svstat
+    S6,
+    /// The built-in supervisor, `sombra daemon`, for systems without a service manager
+    Supervisor,
+}
+
+impl Backend {
+    /// Backend named by SOMBRA_BACKEND, if it is set
+    pub fn from_env() -> crate::Result<Option<Self>> {
+        match std::env::var("SOMBRA_BACKEND") {
+            Ok(name) if !name.is_empty() => name.parse().map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// SOMBRA_BACKEND, or the service manager running on this system, falling back to
+    /// the built-in supervisor
+    pub fn detect() -> crate::Result<Self> {
+        if let Some(backend) = Backend::from_env()? {
+            return Ok(backend);
+        }
+        // Checked the same way as sd_booted()
+        if Path::new("/run/systemd/system").is_dir() {
+            Ok(Backend::Systemd)
+        } else if Path::new("/run/openrc").is_dir() {
+            Ok(Backend::OpenRC)
+        } else if Path::new("/run/service/.s6-svscan").is_dir() {
+            Ok(Backend::S6)
+        } else if Path::new("/run/runit").is_dir() {
+            Ok(Backend::Runit)
+        } else if Path::new("/etc/inittab").is_file() && Path::new("/etc/init.d").is_dir() {
+            Ok(Backend::SysV)
+        } else {
+            Ok(Backend::Supervisor)
+        }
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s {
+            "systemd" => Ok(Backend::Systemd),
+            "openrc" => Ok(Backend::OpenRC),
+            "sysv" => Ok(Backend::SysV),
+            "runit" => Ok(Backend::Runit),
+            "s6" => Ok(Backend::S6),
+            "supervisor" => Ok(Backend::Supervisor),
+            _ => Err(crate::Error::new(crate::ErrorKind::Other,
+                                       format!("Unknown backend {}, expected systemd, openrc, \
+                                                sysv, runit, s6 or supervisor", s))),
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let backend = match self {
+            Backend::Systemd => "systemd",
+            Backend::OpenRC => "openrc",
+            Backend::SysV => "sysv",
+            Backend::Runit => "runit",
+            Backend::S6 => "s6",
+            Backend::Supervisor => "supervisor",
+        };
+        write!(f, "{}", backend)
+    }
+}
@@ -1,14 +1,152 @@
 use structopt::clap::AppSettings;
 use structopt::StructOpt;
-use sombra::Sombra;
+use sombra::{Sombra, Backend, ErrorKind, HealthAction, Liveness, LogFiles, LogLine, LogQuery, Priority, Probe, Readiness, RestartMode, RestartPolicy, Scope, ServiceSpec, ServiceState, ServiceStatus,
+             StartupMode};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use colored::*;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "sombra")]
-enum CLIArgs {
+struct CLIArgs {
+    /// Manage services of the current user, without root (linux only)
+    #[structopt(long, global = true)]
+    user: bool,
+    /// Run again through sudo or pkexec when root privileges are needed (linux only)
+    #[structopt(long, global = true)]
+    elevate: bool,
+    /// Install into this root directory, such as the staging root of an image, without
+    /// starting anything (linux only)
+    #[structopt(long, global = true)]
+    root: Option<String>,
+    /// Service manager to drive: systemd, openrc, sysv, runit, s6 or supervisor, detected when omitted (linux only)
+    #[structopt(long, global = true)]
+    backend: Option<Backend>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+#[allow(clippy::large_enum_variant)] // Parsed once, boxing would only obscure the fields
+enum Command {
     /// Create a service and start it
     #[structopt(setting = AppSettings::AllowLeadingHyphen)]
     Create {
+        /// Startup mode of service: manual, boot or disabled
+        #[structopt(long, default_value = "manual")]
+        startup: StartupMode,
+        /// Description of service
+        #[structopt(long)]
+        description: Option<String>,
+        /// Environment variable of service, as KEY=VALUE
+        #[structopt(long = "env", number_of_values = 1, parse(try_from_str = parse_env))]
+        env: Vec<(String, String)>,
+        /// Dotenv-style file with environment variables of service
+        #[structopt(long)]
+        env_file: Option<String>,
+        /// Variable to copy from the current environment to service
+        #[structopt(long, number_of_values = 1)]
+        inherit_env: Vec<String>,
+        /// Working directory of service, must exist
+        #[structopt(long)]
+        workdir: Option<String>,
+        /// Use the directory of the executable as working directory of service
+        #[structopt(long, conflicts_with = "workdir")]
+        workdir_from_exe: bool,
+        /// When to restart the service: never, on-failure, always or on-abnormal
+        #[structopt(long, default_value = "never")]
+        restart: RestartMode,
+        /// Delay before restarting, as 500ms, 10s, 5m or 1h
+        #[structopt(long, parse(try_from_str = parse_duration))]
+        restart_delay: Option<Duration>,
+        /// Give up after this many restarts within --restart-interval
+        #[structopt(long, requires = "restart-interval")]
+        restart_burst: Option<u32>,
+        /// Interval in which --restart-burst restarts are allowed
+        #[structopt(long, requires = "restart-burst", parse(try_from_str = parse_duration))]
+        restart_interval: Option<Duration>,
+        /// Grow the restart delay exponentially up to this delay
+        #[structopt(long, parse(try_from_str = parse_duration))]
+        restart_max_delay: Option<Duration>,
+        /// Number of restarts to reach --restart-max-delay
+        #[structopt(long, default_value = "5")]
+        restart_steps: u32,
+        /// Exit code considered a success, besides 0
+        #[structopt(long, number_of_values = 1)]
+        success_exit_code: Vec<i32>,
+        /// Exit code that never triggers a restart
+        #[structopt(long, number_of_values = 1)]
+        prevent_restart_exit_code: Vec<i32>,
+        /// Account the service runs as
+        #[structopt(long)]
+        run_as: Option<String>,
+        /// Primary group of service process
+        #[structopt(long)]
+        group: Option<String>,
+        /// Supplementary group of service process
+        #[structopt(long, number_of_values = 1)]
+        supplementary_group: Vec<String>,
+        /// Capability granted to service process, as net_bind_service
+        #[structopt(long, number_of_values = 1)]
+        ambient_capability: Vec<String>,
+        /// Wait until a TCP connection to this address is accepted
+        #[structopt(long, group = "ready")]
+        ready_tcp: Option<String>,
+        /// Wait until a GET to this http:// URL answers with a 2xx status
+        #[structopt(long, group = "ready")]
+        ready_http: Option<String>,
+        /// Wait until this file exists
+        #[structopt(long, group = "ready")]
+        ready_file: Option<String>,
+        /// Wait until this command, with its arguments split on spaces, exits with 0
+        #[structopt(long, group = "ready")]
+        ready_command: Option<String>,
+        /// Wait until a line logged by the service matches this regex (linux only)
+        #[structopt(long, group = "ready")]
+        ready_log: Option<String>,
+        /// How long to wait for the service to be ready
+        #[structopt(long, parse(try_from_str = parse_duration))]
+        ready_timeout: Option<Duration>,
+        /// Check with `sombra monitor` that this address accepts TCP connections
+        #[structopt(long, group = "live")]
+        live_tcp: Option<String>,
+        /// Check with `sombra monitor` that a GET to this http:// URL answers with a 2xx status
+        #[structopt(long, group = "live")]
+        live_http: Option<String>,
+        /// Check with `sombra monitor` that this command, split on spaces, exits with 0
+        #[structopt(long, group = "live")]
+        live_command: Option<String>,
+        /// Delay between two liveness checks
+        #[structopt(long, default_value = "10s", parse(try_from_str = parse_duration))]
+        live_interval: Duration,
+        /// Failed liveness checks in a row before acting
+        #[structopt(long, default_value = "3")]
+        live_threshold: u32,
+        /// Action on an unhealthy service: restart or stop
+        #[structopt(long, default_value = "restart", possible_values = &["restart", "stop"])]
+        live_action: String,
+        /// Command run on an unhealthy service instead of the action, split on spaces
+        #[structopt(long)]
+        live_hook: Option<String>,
+        /// File receiving stdout of service, and stderr unless --stderr-file is given
+        #[structopt(long)]
+        stdout_file: Option<String>,
+        /// File receiving stderr of service
+        #[structopt(long, requires = "stdout-file")]
+        stderr_file: Option<String>,
+        /// Empty the output files every time the service starts instead of appending
+        #[structopt(long, requires = "stdout-file")]
+        log_truncate: bool,
+        /// Rotate an output file past this size, as 512K, 10M or 1G. Under systemd, OpenRC
+        /// and SysV it is checked at start and while sombra monitor runs
+        #[structopt(long, requires = "stdout-file", parse(try_from_str = parse_size))]
+        log_max_size: Option<u64>,
+        /// Rotated output files to keep
+        #[structopt(long, default_value = "5")]
+        log_retain: u32,
+        /// Keep the service running after the user logs out, with --user
+        #[structopt(long)]
+        linger: bool,
         /// Name of service
         name: String,
         /// Path of service executable
@@ -18,32 +156,470 @@ enum CLIArgs {
     },
     /// Delete a service from system
     Delete {
+        /// Delete the service even if it was not created by sombra
+        #[structopt(long)]
+        force: bool,
+        /// Name of service
+        name: String
+    },
+    /// List services created by sombra
+    List,
+    /// Start a stopped service
+    Start {
+        /// Name of service
+        name: String
+    },
+    /// Stop a service without removing it
+    Stop {
+        /// Name of service
+        name: String
+    },
+    /// Stop and start a service again
+    Restart {
+        /// Name of service
+        name: String
+    },
+    /// Start a service on every boot
+    Enable {
+        /// Name of service
+        name: String
+    },
+    /// Stop starting a service on boot
+    Disable {
+        /// Name of service
+        name: String
+    },
+    /// Show the state of a service, exiting with an LSB status code
+    Status {
+        /// Name of service
+        name: String
+    },
+    /// Run liveness checks of services until interrupted
+    Monitor,
+    /// Run the services of the built-in supervisor backend until interrupted (linux only)
+    Daemon,
+    /// Show what a service logged
+    Logs {
+        /// Number of most recent lines to show
+        #[structopt(short = "n", long)]
+        lines: Option<usize>,
+        /// Show lines logged after this time, as a duration ago like 10m or @<unix seconds>
+        #[structopt(long, parse(try_from_str = parse_time))]
+        since: Option<SystemTime>,
+        /// Show lines logged before this time, as a duration ago like 10m or @<unix seconds>
+        #[structopt(long, parse(try_from_str = parse_time))]
+        until: Option<SystemTime>,
+        /// Show lines of this priority or more severe: emerg, alert, crit, err, warning,
+        /// notice, info, debug or 0-7
+        #[structopt(short = "p", long)]
+        priority: Option<Priority>,
+        /// Keep showing new lines until interrupted
+        #[structopt(short = "f", long)]
+        follow: bool,
         /// Name of service
         name: String
     },
 }
 
-fn cli_handler(args: CLIArgs) -> sombra::Result<String> {
-    let success_msg = match args {
-        CLIArgs::Create {name, path, mut args } => {
-            args.retain(|x| x != "");
-            sombra::build(&name, &path, args)?.create()?;
-            format!("Service {} created with success", name)
+fn parse_env(s: &str) -> Result<(String, String), String> {
+    let mut pair = s.splitn(2, '=');
+    match (pair.next(), pair.next()) {
+        (Some(key), Some(value)) => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected KEY=VALUE, found {}", s)),
+    }
+}
+
+fn split_command(command: &str) -> Option<(PathBuf, Vec<String>)> {
+    let mut words = command.split_whitespace().map(String::from);
+    words.next().map(|program| (PathBuf::from(program), words.collect()))
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value.parse()
+        .map_err(|_| format!("expected a duration like 10s, found {}", s))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(value)),
+        "" | "s" => Ok(Duration::from_secs(value)),
+        "m" | "min" => Ok(Duration::from_secs(value * 60)),
+        "h" => Ok(Duration::from_secs(value * 60 * 60)),
+        _ => Err(format!("unknown duration unit {}", unit)),
+    }
+}
+
+fn parse_size(s: &str) -> Result<u64, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let value: u64 = value.parse()
+        .map_err(|_| format!("expected a size like 10M, found {}", s))?;
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return Err(format!("unknown size unit {}", unit)),
+    };
+    value.checked_mul(multiplier).ok_or_else(|| format!("{} is too large", s))
+}
+
+fn parse_time(s: &str) -> Result<SystemTime, String> {
+    match s.strip_prefix('@') {
+        Some(secs) => secs.parse().map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+            .map_err(|_| format!("expected @<unix seconds>, found {}", s)),
+        None => SystemTime::now().checked_sub(parse_duration(s)?)
+            .ok_or_else(|| format!("{} is too far in the past", s)),
+    }
+}
+
+// Formats as RFC 3339 in UTC, using the days-to-civil conversion of the proleptic calendar
+fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, rest) = ((secs / 86400) as i64, secs % 86400);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day,
+            rest / 3600, rest % 3600 / 60, rest % 60)
+}
+
+fn log_line_msg(line: &LogLine) -> String {
+    let mut msg = String::new();
+    if let Some(timestamp) = line.timestamp {
+        msg.push_str(&format!("{} ", format_time(timestamp)));
+    }
+    if let Some(priority) = line.priority {
+        msg.push_str(&format!("{:<7} ", priority));
+    }
+    msg.push_str(&line.message);
+    msg
+}
+
+// Exit codes defined by the LSB for the init script `status` action
+const LSB_STATUS_UNKNOWN: i32 = 4;
+
+fn lsb_status_code(state: ServiceState) -> i32 {
+    match state {
+        ServiceState::Running => 0,
+        ServiceState::Failed => 1,
+        ServiceState::Stopped => 3,
+        ServiceState::NotInstalled => LSB_STATUS_UNKNOWN,
+    }
+}
+
+fn status_msg(name: &str, status: &ServiceStatus) -> String {
+    let mut details = vec![];
+    if let Some(pid) = status.main_pid {
+        details.push(format!("pid {}", pid));
+    }
+    if let Some(uptime) = status.uptime {
+        details.push(format!("up {}s", uptime.as_secs()));
+    }
+    if let Some(code) = status.last_exit_code {
+        details.push(format!("last exit code {}", code));
+    }
+    match &status.health {
+        Some(health) if health.healthy => details.push("healthy".to_string()),
+        Some(health) => details.push(format!("unhealthy after {} check(s): {}",
+                                             health.consecutive_failures, health.last_result)),
+        None => (),
+    }
+
+    if details.is_empty() {
+        format!("Service {} is {}", name, status.state)
+    } else {
+        format!("Service {} is {} ({})", name, status.state, details.join(", "))
+    }
+}
+
+// Scope and root directory given before the command
+fn base_spec(name: &str, path: &str, scope: Scope, root: &Option<String>) -> ServiceSpec {
+    let spec = ServiceSpec::new(name, path).scope(scope);
+    match root {
+        Some(root) => spec.root(root),
+        None => spec,
+    }
+}
+
+// Service to manage by name, its executable only matters to `create`
+fn service(name: &str, scope: Scope, root: &Option<String>) -> sombra::Result<impl Sombra> {
+    sombra::from_spec(base_spec(name, ".", scope, root))
+}
+
+fn cli_handler(args: CLIArgs) -> sombra::Result<(String, i32)> {
+    let scope = if args.user { Scope::User } else { Scope::System };
+    let root = args.root;
+    if let (Some(_), Command::List) | (Some(_), Command::Monitor) | (Some(_), Command::Daemon)
+        = (&root, &args.command) {
+        return Err(sombra::Error::new(ErrorKind::Unsupported,
+                                      "--root only applies to commands on one service"
+                                          .to_string()));
+    }
+    let success = match args.command {
+        Command::Create {startup, description, env, env_file, inherit_env, workdir,
+                          workdir_from_exe, restart, restart_delay, restart_burst,
+                          restart_interval, restart_max_delay, restart_steps, success_exit_code,
+                          prevent_restart_exit_code, run_as, group, supplementary_group,
+                          ambient_capability, ready_tcp, ready_http, ready_file, ready_command,
+                          ready_log, ready_timeout, live_tcp, live_http, live_command,
+                          live_interval, live_threshold, live_action, live_hook, stdout_file,
+                          stderr_file, log_truncate, log_max_size, log_retain, linger, name,
+                          path, mut args } => {
+            args.retain(|x| !x.is_empty());
+            let mut spec = base_spec(&name, &path, scope, &root)
+                .args(args)
+                .startup(startup);
+            if linger {
+                spec = spec.linger();
+            }
+            if let Some(description) = description {
+                spec = spec.description(&description);
+            }
+            if let Some(env_file) = env_file {
+                spec = spec.env_file(&env_file);
+            }
+            for key in inherit_env {
+                spec = spec.inherit_env(&key);
+            }
+            for (key, value) in env {
+                spec = spec.env(&key, &value);
+            }
+            if let Some(workdir) = workdir {
+                spec = spec.working_directory(&workdir);
+            } else if workdir_from_exe {
+                spec = spec.working_directory_from_executable();
+            }
+            let mut policy = RestartPolicy::new(restart);
+            if let Some(delay) = restart_delay {
+                policy = policy.delay(delay);
+            }
+            if let (Some(count), Some(interval)) = (restart_burst, restart_interval) {
+                policy = policy.burst(count, interval);
+            }
+            if let Some(max_delay) = restart_max_delay {
+                policy = policy.backoff(max_delay, restart_steps);
+            }
+            for code in success_exit_code {
+                policy = policy.success_exit_code(code);
+            }
+            for code in prevent_restart_exit_code {
+                policy = policy.prevent_exit_code(code);
+            }
+            spec = spec.restart(policy);
+            if let Some(user) = run_as {
+                spec = spec.user(&user);
+            }
+            if let Some(group) = group {
+                spec = spec.group(&group);
+            }
+            for group in supplementary_group {
+                spec = spec.supplementary_group(&group);
+            }
+            for capability in ambient_capability {
+                spec = spec.ambient_capability(&capability);
+            }
+            let probe = if let Some(address) = ready_tcp {
+                Some(Probe::Tcp(address))
+            } else if let Some(url) = ready_http {
+                Some(Probe::Http(url))
+            } else if let Some(path) = ready_file {
+                Some(Probe::File(path.into()))
+            } else if let Some(command) = ready_command {
+                split_command(&command).map(|(program, args)| Probe::Command(program, args))
+            } else {
+                ready_log.map(Probe::Log)
+            };
+            if let Some(probe) = probe {
+                let mut readiness = Readiness::new(probe);
+                if let Some(timeout) = ready_timeout {
+                    readiness = readiness.timeout(timeout);
+                }
+                spec = spec.readiness(readiness);
+            }
+            let probe = if let Some(address) = live_tcp {
+                Some(Probe::Tcp(address))
+            } else if let Some(url) = live_http {
+                Some(Probe::Http(url))
+            } else {
+                live_command.as_deref().and_then(split_command)
+                    .map(|(program, args)| Probe::Command(program, args))
+            };
+            if let Some(probe) = probe {
+                let action = match live_hook.as_deref().and_then(split_command) {
+                    Some((program, args)) => HealthAction::Hook(program, args),
+                    None if live_action == "stop" => HealthAction::Stop,
+                    None => HealthAction::Restart,
+                };
+                spec = spec.liveness(Liveness::new(probe)
+                    .interval(live_interval)
+                    .threshold(live_threshold)
+                    .action(action));
+            }
+            if let Some(stdout_file) = stdout_file {
+                let mut files = LogFiles::new(&stdout_file);
+                if let Some(stderr_file) = stderr_file {
+                    files = files.stderr(&stderr_file);
+                }
+                if log_truncate {
+                    files = files.truncate();
+                }
+                if let Some(max_size) = log_max_size {
+                    files = files.rotate(max_size, log_retain);
+                }
+                spec = spec.log_files(files);
+            }
+            sombra::from_spec(spec)?.create()?;
+            (format!("Service {} created with success", name), 0)
         },
-        CLIArgs::Delete {name} => {
-            sombra::build(&name, ".", vec![])?.delete()?;
-            format!("Service {} deleted with success", name)
-        }
+        Command::Delete {force, name} => {
+            let service = service(&name, scope, &root)?;
+            if force {
+                service.force_delete()?;
+            } else {
+                service.delete()?;
+            }
+            (format!("Service {} deleted with success", name), 0)
+        },
+        Command::List => {
+            let services = sombra::list_in(scope)?;
+            let mut msg = format!("{} service(s) created by sombra", services.len());
+            for service in services {
+                let state = match service.status() {
+                    Ok(status) => status.state.to_string(),
+                    Err(_) => "unknown".to_string(),
+                };
+                msg.push_str(&format!("\n{}\t{}\t{}", service.name(), state,
+                                      service.executable().display()));
+            }
+            (msg, 0)
+        },
+        Command::Start {name} => {
+            service(&name, scope, &root)?.start()?;
+            (format!("Service {} started with success", name), 0)
+        },
+        Command::Stop {name} => {
+            service(&name, scope, &root)?.stop()?;
+            (format!("Service {} stopped with success", name), 0)
+        },
+        Command::Restart {name} => {
+            service(&name, scope, &root)?.restart()?;
+            (format!("Service {} restarted with success", name), 0)
+        },
+        Command::Enable {name} => {
+            service(&name, scope, &root)?.enable()?;
+            (format!("Service {} enabled with success", name), 0)
+        },
+        Command::Disable {name} => {
+            service(&name, scope, &root)?.disable()?;
+            (format!("Service {} disabled with success", name), 0)
+        },
+        Command::Status {name} => {
+            let status = service(&name, scope, &root)?.status()?;
+            (status_msg(&name, &status), lsb_status_code(status.state))
+        },
+        Command::Logs {lines, since, until, priority, follow, name} => {
+            let mut query = LogQuery::new();
+            if let Some(lines) = lines {
+                query = query.lines(lines);
+            }
+            if let Some(since) = since {
+                query = query.since(since);
+            }
+            if let Some(until) = until {
+                query = query.until(until);
+            }
+            if let Some(priority) = priority {
+                query = query.priority(priority);
+            }
+            if follow {
+                query = query.follow();
+            }
+
+            let mut count = 0;
+            for line in service(&name, scope, &root)?.logs(&query)? {
+                println!("{}", log_line_msg(&line?));
+                count += 1;
+            }
+            (format!("{} line(s) logged by service {}", count, name), 0)
+        },
+        Command::Daemon => {
+            sombra::daemon(scope)?;
+            ("sombra daemon stopped its services".to_string(), 0)
+        },
+        Command::Monitor => sombra::monitor().scope(scope).run(|e| println!("[{}] {}", "ERR".red(), e)),
     };
 
-    Ok(success_msg)
+    Ok(success)
+}
+
+// Runs the same command line again as root, returning its exit code
+#[cfg(unix)]
+fn elevate() -> sombra::Result<i32> {
+    let exe = std::env::current_exe()?;
+    let args: Vec<_> = std::env::args_os().skip(1).collect();
+    let mut sudo = std::process::Command::new("sudo");
+    sudo.arg("--").arg(&exe).args(&args);
+    // pkexec starts from another directory, relative paths need the current one back
+    let mut pkexec = std::process::Command::new("pkexec");
+    pkexec.args(["/bin/sh", "-c", "cd -- \"$1\" && shift && exec \"$@\"", "sh"])
+        .arg(std::env::current_dir()?)
+        .arg(&exe)
+        .args(&args);
+
+    for mut command in [sudo, pkexec] {
+        match command.status() {
+            Ok(status) => return Ok(status.code().unwrap_or(1)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err(sombra::Error::new(ErrorKind::PermissionDenied,
+                           "Neither sudo nor pkexec is available to gain root privileges"
+                               .to_string()))
+}
+
+#[cfg(not(unix))]
+fn elevate() -> sombra::Result<i32> {
+    Err(sombra::Error::new(ErrorKind::Unsupported,
+                           "Elevation is not supported on this platform, run from an \
+                            administrator prompt".to_string()))
 }
 
 fn main() {
     let args = CLIArgs::from_args();
-
-    match cli_handler(args) {
-        Ok(success_msg) => println!("[{}] {}", "OK".green(), success_msg),
-        Err(e) => println!("[{}] {}", "ERR".red(), e.to_string()),
+    // The library reads the backend from the environment, like every nested sombra does
+    if let Some(backend) = args.backend {
+        std::env::set_var("SOMBRA_BACKEND", backend.to_string());
     }
+    // Errors are reported on stdout; only `status` also signals them through the exit code
+    let error_code = match args.command {
+        Command::Status {..} => LSB_STATUS_UNKNOWN,
+        _ => 0,
+    };
+    let can_elevate = args.elevate;
+
+    let error = match cli_handler(args) {
+        Ok((success_msg, code)) => {
+            println!("[{}] {}", "OK".green(), success_msg);
+            std::process::exit(code);
+        },
+        Err(e) if *e.kind() == ErrorKind::PermissionDenied && can_elevate => match elevate() {
+            Ok(code) => std::process::exit(code),
+            Err(e) => e,
+        },
+        Err(e) if *e.kind() == ErrorKind::PermissionDenied => {
+            println!("[{}] {}", "ERR".red(), e);
+            println!("Run it as root, or pass --elevate to go through sudo or pkexec");
+            std::process::exit(error_code);
+        },
+        Err(e) => e,
+    };
+    println!("[{}] {}", "ERR".red(), error);
+    std::process::exit(error_code);
 }
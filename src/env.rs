@@ -0,0 +1,106 @@
+use std::path::Path;
+
+/// Checks a variable name against the portable `[A-Za-z_][A-Za-z0-9_]*` grammar
+pub fn check_name(name: &str) -> crate::Result<()> {
+    let mut chars = name.chars();
+    let valid = match chars.next() {
+        Some(first) => (first.is_ascii_alphabetic() || first == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        None => false,
+    };
+
+    if valid {
+        Ok(())
+    } else {
+        Err(crate::Error::new(crate::ErrorKind::Other,
+                              format!("Invalid environment variable name {:?}", name)))
+    }
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        return value[1..value.len() - 1].to_string();
+    }
+    if !(value.len() >= 2 && value.starts_with('"') && value.ends_with('"')) {
+        // Unquoted values end at an inline comment
+        return match value.find(" #") {
+            Some(comment) => value[..comment].trim_end().to_string(),
+            None => value.to_string(),
+        };
+    }
+
+    let mut unquoted = String::new();
+    let mut chars = value[1..value.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unquoted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unquoted.push('\n'),
+            Some('t') => unquoted.push('\t'),
+            Some('r') => unquoted.push('\r'),
+            Some(other) => unquoted.push(other),
+            None => unquoted.push('\\'),
+        }
+    }
+    unquoted
+}
+
+/// Parses a dotenv-style file: `KEY=value` lines, optionally prefixed by `export`,
+/// with `#` comments and single or double quoted values
+pub fn parse_env_file(content: &str) -> crate::Result<Vec<(String, String)>> {
+    let mut vars = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+
+        let mut pair = line.splitn(2, '=');
+        match (pair.next(), pair.next()) {
+            (Some(key), Some(value)) => {
+                let key = key.trim();
+                check_name(key)?;
+                vars.push((key.to_string(), unquote(value)));
+            },
+            _ => return Err(crate::Error::new(crate::ErrorKind::Other,
+                                              format!("Expected KEY=value at line {}",
+                                                      number + 1))),
+        }
+    }
+    Ok(vars)
+}
+
+pub fn read_env_file(path: &Path) -> crate::Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| crate::Error::new(crate::ErrorKind::Io, e.to_string())
+            .content(path.display().to_string()))?;
+    parse_env_file(&content)
+        .map_err(|e| e.content(path.display().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dotenv() {
+        let content = "# settings\n\
+                       PORT=30222\n\
+                       export HOST = 0.0.0.0 # listen everywhere\n\
+                       GREETING=\"hello \\\"sombra\\\"\\nbye\"\n\
+                       RAW='$HOME %h'\n\
+                       \n";
+        assert_eq!(parse_env_file(content), Ok(vec![
+            ("PORT".to_string(), "30222".to_string()),
+            ("HOST".to_string(), "0.0.0.0".to_string()),
+            ("GREETING".to_string(), "hello \"sombra\"\nbye".to_string()),
+            ("RAW".to_string(), "$HOME %h".to_string()),
+        ]));
+        assert!(parse_env_file("NOT A PAIR").is_err());
+        assert!(parse_env_file("1PORT=1").is_err());
+    }
+}
@@ -3,6 +3,7 @@ pub struct Error {
     kind: ErrorKind,
     desc: String,
     content: Option<String>,
+    exit_code: Option<i32>,
 }
 
 impl Error {
@@ -11,6 +12,7 @@ impl Error {
             kind,
             desc,
             content: None,
+            exit_code: None,
         }
     }
 
@@ -18,6 +20,20 @@ impl Error {
         self.content = Some(content);
         self
     }
+
+    /// Exit code of the failed service manager command
+    pub fn exit_code(mut self, code: i32) -> Self {
+        self.exit_code = Some(code);
+        self
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn code(&self) -> Option<i32> {
+        self.exit_code
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -26,15 +42,26 @@ pub enum ErrorKind {
     Io,
     Utf8,
     WindowsService,
+    NotManaged,
+    Unsupported,
+    InvalidName,
+    ServiceManager,
+    Timeout,
+    /// The operation needs more privileges than the process has
+    PermissionDenied,
 }
 
-impl ToString for Error {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if let Some(content) = &self.content {
-            format!("<{:?}> {}: {}", self.kind, content, self.desc)
+            write!(f, "<{:?}> {}: {}", self.kind, content, self.desc)?;
         } else {
-            format!("<{:?}> {}", self.kind, self.desc)
+            write!(f, "<{:?}> {}", self.kind, self.desc)?;
+        }
+        if let Some(code) = self.exit_code {
+            write!(f, " (exit code {})", code)?;
         }
+        Ok(())
     }
 }
 
@@ -0,0 +1,338 @@
+use crate::{Probe, Scope, Sombra};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_THRESHOLD: u32 = 3;
+// How often the monitor looks for created or deleted services
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+/// What the monitor does once a service fails its liveness probe too many times in a row
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthAction {
+    Restart,
+    Stop,
+    /// Runs the program, with the service name in `SOMBRA_SERVICE`
+    Hook(PathBuf, Vec<String>),
+}
+
+/// Probe `sombra monitor` runs periodically against a running service
+#[derive(Debug, Clone, PartialEq)]
+pub struct Liveness {
+    pub(crate) probe: Probe,
+    pub(crate) interval: Duration,
+    pub(crate) threshold: u32,
+    pub(crate) action: HealthAction,
+}
+
+impl Liveness {
+    pub fn new(probe: Probe) -> Self {
+        Liveness {
+            probe,
+            interval: DEFAULT_INTERVAL,
+            threshold: DEFAULT_THRESHOLD,
+            action: HealthAction::Restart,
+        }
+    }
+
+    /// Delay between two runs of the probe
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Consecutive failures needed before the action is taken
+    pub fn threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold.max(1);
+        self
+    }
+
+    pub fn action(mut self, action: HealthAction) -> Self {
+        self.action = action;
+        self
+    }
+
+    pub(crate) fn validate(&self) -> crate::Result<()> {
+        if let Probe::Log(_) = self.probe {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Log probes cannot check liveness".to_string())
+                .content(self.probe.to_string()));
+        }
+        self.probe.validate()
+    }
+}
+
+/// Last liveness result of a service, as recorded by the monitor
+#[derive(Debug, Clone, PartialEq)]
+pub struct Health {
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    /// Why the last probe failed, empty when it passed
+    pub last_result: String,
+    pub checked: SystemTime,
+}
+
+impl std::fmt::Display for HealthAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthAction::Restart => write!(f, "restart"),
+            HealthAction::Stop => write!(f, "stop"),
+            HealthAction::Hook(program, _) => write!(f, "hook {}", program.display()),
+        }
+    }
+}
+
+// Results are shared with `status` through one small file per service
+pub(crate) fn health_dir(scope: Scope) -> PathBuf {
+    if scope == Scope::User {
+        // A user cannot write to /run, the runtime directory is its counterpart
+        return match std::env::var("XDG_RUNTIME_DIR") {
+            Ok(dir) => Path::new(&dir).join("sombra").join("health"),
+            Err(_) => user_temp_dir().join("health"),
+        };
+    }
+    if cfg!(target_os = "windows") {
+        let program_data = std::env::var("ProgramData")
+            .unwrap_or_else(|_| "C:\\ProgramData".to_string());
+        Path::new(&program_data).join("sombra").join("health")
+    } else {
+        PathBuf::from("/run/sombra/health")
+    }
+}
+
+/// Health recorded in the directory for the service, if a monitor has probed it
+pub(crate) fn load(dir: &Path, name: &str) -> Option<Health> {
+    let content = std::fs::read_to_string(dir.join(name)).ok()?;
+    let mut values: HashMap<&str, &str> = content.lines()
+        .filter_map(|line| {
+            let mut pair = line.splitn(2, '=');
+            Some((pair.next()?, pair.next()?))
+        })
+        .collect();
+    Some(Health {
+        healthy: values.remove("Healthy")? == "true",
+        consecutive_failures: values.remove("Failures")?.parse().ok()?,
+        last_result: values.remove("Result").unwrap_or_default().to_string(),
+        checked: UNIX_EPOCH + Duration::from_secs(values.remove("Checked")?.parse().ok()?),
+    })
+}
+
+fn save(dir: &Path, name: &str, health: &Health) -> crate::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let checked = health.checked.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let content = format!("Healthy={}\nFailures={}\nChecked={}\nResult={}\n",
+                          health.healthy, health.consecutive_failures, checked,
+                          health.last_result.replace(['\r', '\n'], " "));
+    // Written aside and renamed, so `status` never reads half a file
+    let partial = dir.join(format!(".{}", name));
+    std::fs::write(&partial, content)?;
+    std::fs::rename(&partial, dir.join(name))?;
+    Ok(())
+}
+
+fn forget(dir: &Path, name: &str) {
+    let _ = std::fs::remove_file(dir.join(name));
+}
+
+#[cfg(unix)]
+fn user_temp_dir() -> PathBuf {
+    std::env::temp_dir().join(format!("sombra-{}", unsafe { libc::geteuid() }))
+}
+
+// The temporary directory is already per user on Windows
+#[cfg(not(unix))]
+fn user_temp_dir() -> PathBuf {
+    std::env::temp_dir().join("sombra")
+}
+
+struct Check {
+    next: Instant,
+    failures: u32,
+}
+
+/// Runs the liveness probes of every service created by sombra and rotates their output files
+pub struct Monitor<S: Sombra> {
+    checks: HashMap<String, Check>,
+    services: Vec<S>,
+    reloaded: Option<Instant>,
+    scope: Scope,
+}
+
+impl<S: Sombra> Default for Monitor<S> {
+    fn default() -> Self {
+        Monitor {
+            checks: HashMap::new(),
+            services: vec![],
+            reloaded: None,
+            scope: Scope::default(),
+        }
+    }
+}
+
+impl<S: Sombra> Monitor<S> {
+    pub fn new() -> Self {
+        Monitor::default()
+    }
+
+    /// Monitors the services of this scope instead of the system ones
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Probes services forever, errors are handed to `on_error` and do not stop the monitor
+    pub fn run<F: FnMut(crate::Error)>(&mut self, mut on_error: F) -> ! {
+        loop {
+            let (wait, errors) = self.tick();
+            errors.into_iter().for_each(&mut on_error);
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Probes the services that are due, returning how long to wait for the next one
+    pub fn tick(&mut self) -> (Duration, Vec<crate::Error>) {
+        let now = Instant::now();
+        let mut errors = vec![];
+        if self.reloaded.is_none_or(|reloaded| now >= reloaded + RELOAD_INTERVAL) {
+            if let Err(e) = self.reload() {
+                errors.push(e);
+            }
+            self.reloaded = Some(now);
+        }
+
+        let mut wait = RELOAD_INTERVAL;
+        for service in &self.services {
+            if let Some(log_files) = &service.spec().log_files {
+                if let Err(e) = log_files.rotate_files() {
+                    errors.push(e);
+                }
+            }
+            let liveness = match &service.spec().liveness {
+                Some(liveness) => liveness,
+                None => continue,
+            };
+            let check = self.checks.entry(service.name().to_string())
+                .or_insert(Check { next: now, failures: 0 });
+            if check.next <= now {
+                match Monitor::probe(&health_dir(self.scope), service, liveness,
+                                     check.failures) {
+                    Ok(failures) => check.failures = failures,
+                    Err(e) => errors.push(e),
+                }
+                check.next = now + liveness.interval;
+            }
+            wait = wait.min(check.next.saturating_duration_since(now));
+        }
+        (wait, errors)
+    }
+
+    fn reload(&mut self) -> crate::Result<()> {
+        self.services = S::list_in(self.scope)?;
+        let services = &self.services;
+        let dir = health_dir(self.scope);
+        self.checks.retain(|name, _| {
+            let monitored = services.iter()
+                .any(|s| s.name() == name && s.spec().liveness.is_some());
+            if !monitored {
+                forget(&dir, name);
+            }
+            monitored
+        });
+        Ok(())
+    }
+
+    // Runs the probe once, returning the updated count of consecutive failures
+    pub(crate) fn probe(dir: &Path, service: &S, liveness: &Liveness, failures: u32)
+             -> crate::Result<u32> {
+        // A service stopped on purpose is not unhealthy
+        if !service.status()?.is_running() {
+            forget(dir, service.name());
+            return Ok(0);
+        }
+
+        let deadline = Instant::now() + liveness.interval;
+        let result = liveness.probe.check(deadline, || Ok(String::new()));
+        let failures = match &result {
+            Ok(()) => 0,
+            Err(_) => failures + 1,
+        };
+        save(dir, service.name(), &Health {
+            healthy: result.is_ok(),
+            consecutive_failures: failures,
+            last_result: result.err().unwrap_or_default(),
+            checked: SystemTime::now(),
+        })?;
+        if failures < liveness.threshold {
+            return Ok(failures);
+        }
+
+        match &liveness.action {
+            HealthAction::Restart => service.restart()?,
+            HealthAction::Stop => service.stop()?,
+            HealthAction::Hook(program, args) => {
+                let mut child = Command::new(program)
+                    .args(args)
+                    .env("SOMBRA_SERVICE", service.name())
+                    .stdin(Stdio::null())
+                    .spawn()?;
+                let deadline = Instant::now() + liveness.interval;
+                crate::probe::wait(&mut child, deadline).map_err(|e| {
+                    crate::Error::new(crate::ErrorKind::Timeout,
+                                      format!("Liveness hook of {}: {}", service.name(), e))
+                        .content(program.display().to_string())
+                })?;
+            },
+        }
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn health_round_trip() {
+        let dir = std::env::temp_dir().join(format!("sombra-health-{}", std::process::id()));
+        let health = Health {
+            healthy: false,
+            consecutive_failures: 2,
+            last_result: "Connection refused\n(os error 111)".to_string(),
+            checked: UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        };
+        assert_eq!(save(&dir, "tcp_echo", &health).map_err(|e| e.to_string()), Ok(()));
+        let loaded = load(&dir, "tcp_echo");
+        forget(&dir, "tcp_echo");
+        let _ = std::fs::remove_dir(&dir);
+
+        assert_eq!(loaded, Some(Health {
+            last_result: "Connection refused (os error 111)".to_string(),
+            ..health
+        }));
+        assert_eq!(load(&dir, "tcp_echo"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hook_is_killed_after_interval() {
+        use crate::testing::MockSombra;
+        MockSombra::reset();
+        let dir = std::env::temp_dir().join(format!("sombra-hook-{}", std::process::id()));
+        let service = MockSombra::build("tcp_echo", "executables/tcp_echo", vec![]).unwrap();
+        service.create().unwrap();
+        let liveness = Liveness::new(Probe::File(dir.join("missing")))
+            .interval(Duration::from_millis(100))
+            .threshold(1)
+            .action(HealthAction::Hook(PathBuf::from("/bin/sleep"), vec!["10".to_string()]));
+
+        let started = Instant::now();
+        let result = Monitor::<MockSombra>::probe(&dir, &service, &liveness, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let err = result.expect_err("the hook should time out");
+        assert_eq!(err.kind(), &crate::ErrorKind::Timeout);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -1,8 +1,33 @@
 mod result;
 mod error;
+mod status;
+mod startup;
+mod restart;
+mod spec;
+mod marker;
+mod env;
+mod name;
+mod probe;
+mod health;
+mod logs;
+mod output;
+mod scope;
+mod backend;
+pub mod testing;
 
 pub use result::Result;
 pub use error::{Error, ErrorKind};
+pub use status::{ServiceState, ServiceStatus};
+pub use startup::StartupMode;
+pub use restart::{RestartMode, RestartPolicy};
+pub use spec::ServiceSpec;
+pub use name::ServiceName;
+pub use probe::{Probe, Readiness};
+pub use health::{Health, HealthAction, Liveness, Monitor};
+pub use logs::{LogLine, LogQuery, Logs, Priority};
+pub use output::{FileMode, LogFiles};
+pub use scope::Scope;
+pub use backend::Backend;
 
 #[cfg(target_os = "windows")]
 mod windows;
@@ -10,10 +35,48 @@ mod windows;
 mod linux;
 
 pub trait Sombra {
+    fn from_spec(spec: ServiceSpec) -> Result<Self>
+        where Self: std::marker::Sized;
     fn build(name: &str, path: &str, args: Vec<String>) -> Result<Self>
+        where Self: std::marker::Sized {
+        Self::from_spec(ServiceSpec::new(name, path).args(args))
+    }
+    fn list() -> Result<Vec<Self>>
+        where Self: std::marker::Sized {
+        Self::list_in(Scope::System)
+    }
+    fn list_in(scope: Scope) -> Result<Vec<Self>>
         where Self: std::marker::Sized;
+    fn spec(&self) -> &ServiceSpec;
+    fn name(&self) -> &str {
+        self.spec().name()
+    }
+    fn executable(&self) -> &std::path::Path {
+        self.spec().executable()
+    }
     fn create(&self) -> Result<()>;
     fn delete(&self) -> Result<()>;
+    fn force_delete(&self) -> Result<()>;
+    fn start(&self) -> Result<()>;
+    fn stop(&self) -> Result<()>;
+    fn restart(&self) -> Result<()> {
+        self.stop()?;
+        self.start()
+    }
+    fn enable(&self) -> Result<()>;
+    fn disable(&self) -> Result<()>;
+    fn status(&self) -> Result<ServiceStatus>;
+    fn logs(&self, query: &LogQuery) -> Result<Logs>;
+}
+
+#[cfg(target_os = "windows")]
+pub fn from_spec(spec: ServiceSpec) -> Result<windows::sombra_imp::SombraWindows> {
+    windows::sombra_imp::SombraWindows::from_spec(spec)
+}
+
+#[cfg(target_os = "linux")]
+pub fn from_spec(spec: ServiceSpec) -> Result<linux::service::Service> {
+    linux::service::Service::from_spec(spec)
 }
 
 #[cfg(target_os = "windows")]
@@ -22,6 +85,49 @@ pub fn build(name: &str, path: &str, args: Vec<String>) -> Result<windows::sombr
 }
 
 #[cfg(target_os = "linux")]
-pub fn build(name: &str, path: &str, args: Vec<String>) -> Result<linux::sombra_imp::SombraLinux> {
-    linux::sombra_imp::SombraLinux::build(name, path, args)
-}
\ No newline at end of file
+pub fn build(name: &str, path: &str, args: Vec<String>) -> Result<linux::service::Service> {
+    linux::service::Service::build(name, path, args)
+}
+
+#[cfg(target_os = "windows")]
+pub fn list() -> Result<Vec<windows::sombra_imp::SombraWindows>> {
+    windows::sombra_imp::SombraWindows::list()
+}
+
+#[cfg(target_os = "linux")]
+pub fn list() -> Result<Vec<linux::service::Service>> {
+    linux::service::Service::list()
+}
+
+#[cfg(target_os = "windows")]
+pub fn list_in(scope: Scope) -> Result<Vec<windows::sombra_imp::SombraWindows>> {
+    windows::sombra_imp::SombraWindows::list_in(scope)
+}
+
+#[cfg(target_os = "linux")]
+pub fn list_in(scope: Scope) -> Result<Vec<linux::service::Service>> {
+    linux::service::Service::list_in(scope)
+}
+
+#[cfg(target_os = "windows")]
+pub fn monitor() -> Monitor<windows::sombra_imp::SombraWindows> {
+    Monitor::new()
+}
+
+#[cfg(target_os = "linux")]
+pub fn monitor() -> Monitor<linux::service::Service> {
+    Monitor::new()
+}
+
+/// Runs the built-in supervisor of the scope until SIGTERM or SIGINT, see `Backend::Supervisor`
+#[cfg(target_os = "windows")]
+pub fn daemon(_scope: Scope) -> Result<()> {
+    Err(Error::new(ErrorKind::Unsupported,
+                   "The built-in supervisor only runs on Linux".to_string()))
+}
+
+/// Runs the built-in supervisor of the scope until SIGTERM or SIGINT, see `Backend::Supervisor`
+#[cfg(target_os = "linux")]
+pub fn daemon(scope: Scope) -> Result<()> {
+    linux::daemon::run(scope)
+}
@@ -0,0 +1,88 @@
+use std::ffi::{CStr, CString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+const INITIAL_BUFFER: usize = 1024;
+const MAX_BUFFER: usize = 1024 * 1024;
+
+/// Entry of the passwd database
+pub struct User {
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+    pub home: PathBuf,
+}
+
+fn not_found(kind: &str, name: &str) -> crate::Error {
+    crate::Error::new(crate::ErrorKind::Other, format!("{} does not exist", kind))
+        .content(name.to_string())
+}
+
+// Runs one of the reentrant `get*nam_r` lookups, growing the buffer while it reports ERANGE
+fn lookup<T, F>(kind: &str, name: &str, getnam: F) -> crate::Result<T>
+    where F: Fn(&CString, &mut Vec<libc::c_char>) -> (libc::c_int, Option<T>) {
+    let c_name = CString::new(name).map_err(|_| not_found(kind, name))?;
+    let mut buffer = vec![0; INITIAL_BUFFER];
+    loop {
+        match getnam(&c_name, &mut buffer) {
+            (0, Some(entry)) => return Ok(entry),
+            (0, None) => return Err(not_found(kind, name)),
+            (libc::ERANGE, _) if buffer.len() < MAX_BUFFER => {
+                let len = buffer.len() * 2;
+                buffer.resize(len, 0);
+            },
+            (errno, _) => return Err(crate::Error::from(std::io::Error::from_raw_os_error(errno))
+                .content(name.to_string())),
+        }
+    }
+}
+
+/// Looks a user account up in the passwd database
+pub fn user(name: &str) -> crate::Result<User> {
+    lookup("User", name, |c_name, buffer| unsafe {
+        let mut passwd: libc::passwd = std::mem::zeroed();
+        let mut result = std::ptr::null_mut();
+        let errno = libc::getpwnam_r(c_name.as_ptr(), &mut passwd, buffer.as_mut_ptr(),
+                                     buffer.len(), &mut result);
+        let user = if result.is_null() {
+            None
+        } else {
+            Some(User {
+                uid: passwd.pw_uid,
+                gid: passwd.pw_gid,
+                home: PathBuf::from(CStr::from_ptr(passwd.pw_dir).to_string_lossy().as_ref()),
+            })
+        };
+        (errno, user)
+    })
+}
+
+/// Looks the ID of a group up in the group database
+pub fn group(name: &str) -> crate::Result<libc::gid_t> {
+    lookup("Group", name, |c_name, buffer| unsafe {
+        let mut group: libc::group = std::mem::zeroed();
+        let mut result = std::ptr::null_mut();
+        let errno = libc::getgrnam_r(c_name.as_ptr(), &mut group, buffer.as_mut_ptr(),
+                                     buffer.len(), &mut result);
+        (errno, if result.is_null() { None } else { Some(group.gr_gid) })
+    })
+}
+
+/// Root, or an account allowed to write to `dir`, judged by the effective UID since
+/// USER is stale under sudo -E, su and cron
+pub fn is_privileged(dir: &Path) -> bool {
+    if unsafe { libc::geteuid() } == 0 {
+        return true;
+    }
+    // A directory still to be created is as writable as its closest existing ancestor
+    let dir = match dir.ancestors().find(|dir| dir.exists()) {
+        Some(dir) => dir,
+        None => return false,
+    };
+    let path = match CString::new(dir.as_os_str().as_bytes()) {
+        Ok(path) => path,
+        Err(_) => return false,
+    };
+    unsafe {
+        libc::faccessat(libc::AT_FDCWD, path.as_ptr(), libc::W_OK, libc::AT_EACCESS) == 0
+    }
+}
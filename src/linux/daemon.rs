@@ -0,0 +1,546 @@
+use crate::{FileMode, LogFiles, Scope, ServiceSpec, StartupMode};
+use crate::linux::account;
+use crate::linux::supervisor::{StateDir, STOP_TIMEOUT};
+use crate::spec::WorkingDirectory;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+const TICK: Duration = Duration::from_millis(100);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+// Same as the default PATH of systemd services
+const DEFAULT_PATH: &str = "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn terminate(_: libc::c_int) {
+    TERMINATE.store(true, Ordering::SeqCst);
+}
+
+/// Supervises the services of the scope until SIGTERM or SIGINT, then stops them
+pub fn run(scope: Scope) -> crate::Result<()> {
+    let state_dir = StateDir::new(scope)?;
+    std::fs::create_dir_all(state_dir.services())?;
+    let listener = bind(&state_dir.socket())?;
+    let handler = terminate as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+
+    let mut daemon = Daemon::new(scope, state_dir.clone());
+    daemon.boot();
+    while !TERMINATE.load(Ordering::SeqCst) {
+        while let Ok((stream, _)) = listener.accept() {
+            daemon.serve(stream);
+        }
+        daemon.tick(Instant::now());
+        std::thread::sleep(TICK);
+    }
+    daemon.shutdown();
+    let _ = std::fs::remove_file(state_dir.socket());
+    Ok(())
+}
+
+/// Reports what happened to a service without a client asking, such as a failed restart, on
+/// the standard error of `sombra daemon`, where its container runtime or init system collects it
+fn report(message: &str) {
+    eprintln!("sombra daemon: {}", message);
+}
+
+// A socket left behind by a daemon that did not exit cleanly is replaced
+fn bind(socket: &Path) -> crate::Result<UnixListener> {
+    if UnixStream::connect(socket).is_ok() {
+        return Err(crate::Error::new(crate::ErrorKind::Other,
+                                     "sombra daemon is already running".to_string())
+            .content(socket.display().to_string()));
+    }
+    match std::fs::remove_file(socket) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(crate::Error::from(e).content(socket.display().to_string()));
+        },
+        _ => (),
+    }
+    // Whoever can connect controls the services, so only the account of the daemon can. The
+    // socket is bound in a directory no one else can enter and moved once it is restricted
+    let private = socket.with_extension(format!("{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&private);
+    std::fs::DirBuilder::new().mode(0o700).create(&private)
+        .map_err(|e| crate::Error::from(e).content(private.display().to_string()))?;
+    let staged = private.join("socket");
+    let bound = UnixListener::bind(&staged)
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::rename(&staged, socket)?;
+            Ok(listener)
+        })
+        .map_err(|e| crate::Error::from(e).content(socket.display().to_string()));
+    let _ = std::fs::remove_dir_all(&private);
+    let listener = bound?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
+
+/// A service known to the daemon, with its process while it runs
+struct Supervised {
+    spec: ServiceSpec,
+    child: Option<Child>,
+    started: Instant,
+    exit_code: Option<i32>,
+    failed: bool,
+    /// Deadline for SIGKILL once a stop was requested, exits are not restarted then
+    stopping: Option<Instant>,
+    /// Automatic restarts since the last requested start, they grow the delay
+    restarts: u32,
+    restart_at: Option<Instant>,
+    /// Recent starts, counted against the burst limit
+    recent: VecDeque<Instant>,
+}
+
+impl Supervised {
+    fn new(spec: ServiceSpec) -> Self {
+        Supervised {
+            spec,
+            child: None,
+            started: Instant::now(),
+            exit_code: None,
+            failed: false,
+            stopping: None,
+            restarts: 0,
+            restart_at: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    fn spawn(&mut self, log_files: &LogFiles) -> crate::Result<()> {
+        let spec = &self.spec;
+        log_files.rotate_files()?;
+        let (stdout, stderr) = open_log_files(log_files)?;
+        let mut command = Command::new(&spec.executable);
+        command.args(&spec.args)
+            .env_clear()
+            .env("PATH", DEFAULT_PATH)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            // Its own process group, so a stop reaches the children of the service too
+            .process_group(0);
+        match &spec.working_directory {
+            Some(WorkingDirectory::Path(path)) => command.current_dir(path),
+            _ => command.current_dir("/"),
+        };
+
+        let user = match &spec.user {
+            Some(name) => {
+                let user = account::user(name)?;
+                command.env("HOME", &user.home).env("USER", name).env("LOGNAME", name);
+                Some(user)
+            },
+            None => None,
+        };
+        command.envs(spec.environment()?);
+        let gid = match &spec.group {
+            Some(group) => Some(account::group(group)?),
+            None => user.as_ref().map(|user| user.gid),
+        };
+        let uid = user.map(|user| user.uid);
+        let groups = spec.supplementary_groups.iter()
+            .map(|group| account::group(group))
+            .collect::<crate::Result<Vec<_>>>()?;
+        if uid.is_some() || gid.is_some() || !groups.is_empty() {
+            unsafe {
+                command.pre_exec(move || switch_account(uid, gid, &groups));
+            }
+        }
+
+        let child = command.spawn()
+            .map_err(|e| crate::Error::from(e).content(spec.executable.display().to_string()))?;
+        self.child = Some(child);
+        self.started = Instant::now();
+        self.exit_code = None;
+        self.failed = false;
+        Ok(())
+    }
+
+    fn signal(&self, signal: libc::c_int) {
+        if let Some(child) = &self.child {
+            unsafe { libc::killpg(child.id() as libc::pid_t, signal) };
+        }
+    }
+
+    // Requested starts begin a new series of restarts
+    fn start(&mut self, now: Instant, log_files: &LogFiles) -> crate::Result<()> {
+        self.restarts = 0;
+        self.recent.clear();
+        self.recent.push_back(now);
+        self.restart_at = None;
+        self.stopping = None;
+        if self.child.is_some() {
+            return Ok(());
+        }
+        let started = self.spawn(log_files);
+        self.failed = started.is_err();
+        started
+    }
+
+    fn stop(&mut self, now: Instant) {
+        self.restart_at = None;
+        if self.child.is_some() && self.stopping.is_none() {
+            self.signal(libc::SIGTERM);
+            self.stopping = Some(now + STOP_TIMEOUT);
+        }
+    }
+
+    // Reaps the process once it exited and decides on a restart, as systemd would
+    fn check(&mut self, now: Instant, log_files: &LogFiles) {
+        if self.restart_at.is_some_and(|at| at <= now) {
+            self.restart_at = None;
+            if let Err(e) = self.spawn(log_files) {
+                report(&format!("Service {} could not restart: {}", self.spec.name, e));
+                self.failed = true;
+            }
+            return;
+        }
+        let status = match self.child.as_mut().map(Child::try_wait) {
+            Some(Ok(Some(status))) => status,
+            Some(Ok(None)) => {
+                if self.stopping.is_some_and(|deadline| deadline <= now) {
+                    self.signal(libc::SIGKILL);
+                }
+                return;
+            },
+            _ => return,
+        };
+        self.child = None;
+        // Reported like a shell would, 128 plus the signal for a killed process
+        self.exit_code = status.code().or_else(|| status.signal().map(|signal| 128 + signal));
+        if self.stopping.take().is_some() {
+            return;
+        }
+
+        let policy = &self.spec.restart;
+        if !policy.restarts_after(status.code()) {
+            self.failed = !policy.is_success(status.code());
+            return;
+        }
+        if let Some((count, interval)) = policy.burst {
+            self.recent.retain(|&restart| now.duration_since(restart) < interval);
+            if self.recent.len() >= count as usize {
+                report(&format!("Service {} restarted too often, giving up", self.spec.name));
+                self.failed = true;
+                return;
+            }
+            self.recent.push_back(now);
+        }
+        let delays = policy.delays();
+        let delay = delays[(self.restarts as usize).min(delays.len() - 1)];
+        self.restarts += 1;
+        self.restart_at = Some(now + delay);
+    }
+
+    fn status(&self, now: Instant) -> Vec<(&'static str, String)> {
+        // A pending restart counts as failed, like the auto-restart state of systemd
+        let state = match (&self.child, self.failed || self.restart_at.is_some()) {
+            (Some(_), _) => "running",
+            (None, true) => "failed",
+            (None, false) => "stopped",
+        };
+        let mut values = vec![("state", state.to_string())];
+        if let Some(child) = &self.child {
+            values.push(("pid", child.id().to_string()));
+            values.push(("uptime_ms", now.duration_since(self.started).as_millis().to_string()));
+        }
+        if let Some(code) = self.exit_code {
+            values.push(("exit_code", code.to_string()));
+        }
+        values
+    }
+}
+
+fn open_log_files(log_files: &LogFiles) -> crate::Result<(File, File)> {
+    let open = |path: &Path| -> crate::Result<File> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // Appending even after truncation, since rotation truncates under the process
+        let file = OpenOptions::new().create(true).append(true).open(path)
+            .map_err(|e| crate::Error::from(e).content(path.display().to_string()))?;
+        if log_files.mode == FileMode::Truncate {
+            file.set_len(0)?;
+        }
+        Ok(file)
+    };
+    let stdout = open(&log_files.stdout)?;
+    let stderr = match &log_files.stderr {
+        Some(stderr) => open(stderr)?,
+        None => stdout.try_clone()?,
+    };
+    Ok((stdout, stderr))
+}
+
+// Runs in the forked child, groups go first since changing them needs root
+fn switch_account(uid: Option<libc::uid_t>, gid: Option<libc::gid_t>, groups: &[libc::gid_t])
+                  -> std::io::Result<()> {
+    unsafe {
+        if libc::geteuid() == 0 && libc::setgroups(groups.len(), groups.as_ptr()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if let Some(gid) = gid {
+            if libc::setgid(gid) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        if let Some(uid) = uid {
+            if libc::setuid(uid) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Daemon {
+    scope: Scope,
+    state_dir: StateDir,
+    services: HashMap<String, Supervised>,
+}
+
+impl Daemon {
+    fn new(scope: Scope, state_dir: StateDir) -> Self {
+        Daemon {
+            scope,
+            state_dir,
+            services: HashMap::new(),
+        }
+    }
+
+    // Starts the services created with `StartupMode::Boot`, or enabled since
+    fn boot(&mut self) {
+        let names = match self.state_dir.names() {
+            Ok(names) => names,
+            Err(e) => return report(&format!("Cannot list services: {}", e)),
+        };
+        for name in names {
+            match self.state_dir.read_spec(self.scope, &name) {
+                Ok(Some(spec)) if spec.startup == StartupMode::Boot => (),
+                _ => continue,
+            }
+            if let Err(e) = self.handle(&format!("start {}", name), Instant::now()) {
+                report(&format!("Service {} could not start: {}", name, e));
+            }
+        }
+    }
+
+    fn serve(&mut self, stream: UnixStream) {
+        let _ = stream.set_nonblocking(false);
+        let _ = stream.set_read_timeout(Some(REQUEST_TIMEOUT));
+        let mut request = String::new();
+        if BufReader::new(&stream).read_line(&mut request).is_err() {
+            return;
+        }
+        let answer = match self.handle(request.trim(), Instant::now()) {
+            Ok(values) => values.iter()
+                .fold("ok\n".to_string(), |answer, (key, value)| {
+                    answer + &format!("{}={}\n", key, value)
+                }),
+            Err(e) => format!("err {}\n", e.replace('\n', " ")),
+        };
+        let _ = (&stream).write_all(answer.as_bytes());
+    }
+
+    /// Runs one request of a client, such as `start echo`
+    fn handle(&mut self, request: &str, now: Instant)
+              -> Result<Vec<(&'static str, String)>, String> {
+        let mut words = request.split(' ');
+        let (command, name) = match (words.next(), words.next()) {
+            (Some(command), Some(name)) => (command, name),
+            _ => return Err(format!("Invalid request {:?}", request)),
+        };
+        // Names become paths in the state directory
+        crate::ServiceName::new(name).map_err(|e| e.to_string())?;
+        match command {
+            "start" => {
+                // The stored spec may have changed since the last start
+                let spec = self.state_dir.read_spec(self.scope, name)
+                    .map_err(|e| e.to_string())?
+                    .ok_or_else(|| format!("Service {} does not exist", name))?;
+                let log_files = self.state_dir.log_files(&spec);
+                let service = self.services.entry(name.to_string())
+                    .or_insert_with(|| Supervised::new(spec.clone()));
+                service.spec = spec;
+                service.start(now, &log_files).map_err(|e| e.to_string())?;
+                Ok(service.status(now))
+            },
+            "stop" => {
+                if let Some(service) = self.services.get_mut(name) {
+                    service.stop(now);
+                }
+                Ok(vec![])
+            },
+            "status" => Ok(match self.services.get(name) {
+                Some(service) => service.status(now),
+                None => vec![("state", "stopped".to_string())],
+            }),
+            _ => Err(format!("Unknown command {:?}", command)),
+        }
+    }
+
+    fn tick(&mut self, now: Instant) {
+        let state_dir = &self.state_dir;
+        for service in self.services.values_mut() {
+            let log_files = state_dir.log_files(&service.spec);
+            service.check(now, &log_files);
+            if service.child.is_some() {
+                if let Err(e) = log_files.rotate_files() {
+                    report(&format!("Service {} output could not rotate: {}",
+                                    service.spec.name, e));
+                }
+            }
+        }
+        // Deleted services are forgotten once they stopped
+        self.services.retain(|name, service| service.child.is_some()
+            || service.restart_at.is_some()
+            || state_dir.spec_path(name).exists());
+    }
+
+    fn shutdown(&mut self) {
+        let now = Instant::now();
+        for service in self.services.values_mut() {
+            service.stop(now);
+        }
+        while self.services.values().any(|service| service.child.is_some()) {
+            let now = Instant::now();
+            for service in self.services.values_mut() {
+                let log_files = self.state_dir.log_files(&service.spec);
+                service.check(now, &log_files);
+            }
+            std::thread::sleep(TICK);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::marker;
+    use crate::marker::Marker;
+    use crate::{RestartMode, RestartPolicy};
+
+    // Stops the services left running and removes the state directory
+    struct TestDaemon(Daemon);
+
+    impl std::ops::Deref for TestDaemon {
+        type Target = Daemon;
+
+        fn deref(&self) -> &Daemon {
+            &self.0
+        }
+    }
+
+    impl std::ops::DerefMut for TestDaemon {
+        fn deref_mut(&mut self) -> &mut Daemon {
+            &mut self.0
+        }
+    }
+
+    impl Drop for TestDaemon {
+        fn drop(&mut self) {
+            self.0.shutdown();
+            let _ = std::fs::remove_dir_all(&self.0.state_dir.0);
+        }
+    }
+
+    fn daemon(name: &str, specs: &[ServiceSpec]) -> TestDaemon {
+        let dir = std::env::temp_dir().join(format!("sombra-daemon-{}-{}", name,
+                                                    std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let state_dir = StateDir(dir);
+        std::fs::create_dir_all(state_dir.services()).unwrap();
+        for spec in specs {
+            std::fs::write(state_dir.spec_path(&spec.name),
+                           marker::render(&Marker::new(spec))).unwrap();
+        }
+        TestDaemon(Daemon::new(Scope::System, state_dir))
+    }
+
+    fn wait_for<F: FnMut(&mut Daemon) -> bool>(daemon: &mut Daemon, mut done: F) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(daemon) {
+            assert!(Instant::now() < deadline, "timed out");
+            daemon.tick(Instant::now());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn state(daemon: &mut Daemon, name: &str) -> String {
+        let values = daemon.handle(&format!("status {}", name), Instant::now()).unwrap();
+        values[0].1.clone()
+    }
+
+    #[test]
+    fn restarts_until_burst_limit() {
+        let spec = ServiceSpec::new("crash", "/bin/sh")
+            .args(vec!["-c".to_string(), "echo crashed; exit 3".to_string()])
+            .restart(RestartPolicy::new(RestartMode::OnFailure)
+                .delay(Duration::from_millis(10))
+                .burst(2, Duration::from_secs(60)));
+        let mut daemon = daemon("burst", &[spec]);
+        assert!(daemon.handle("start crash", Instant::now()).is_ok());
+        wait_for(&mut daemon, |daemon| {
+            let service = &daemon.services["crash"];
+            service.failed && service.restart_at.is_none() && service.child.is_none()
+        });
+        assert_eq!(daemon.services["crash"].restarts, 1);
+        assert_eq!(state(&mut daemon, "crash"), "failed");
+        assert_eq!(daemon.services["crash"].exit_code, Some(3));
+
+        let log = std::fs::read_to_string(daemon.state_dir.log_files(&daemon.services["crash"].spec)
+            .stdout).unwrap();
+        assert_eq!(log, "crashed\ncrashed\n");
+    }
+
+    #[test]
+    fn stop_is_not_restarted() {
+        let spec = ServiceSpec::new("sleep", "/bin/sleep")
+            .arg("60")
+            .restart(RestartPolicy::new(RestartMode::Always));
+        let mut daemon = daemon("stop", &[spec]);
+        assert!(daemon.handle("start sleep", Instant::now()).is_ok());
+        assert_eq!(state(&mut daemon, "sleep"), "running");
+        assert!(daemon.handle("stop sleep", Instant::now()).is_ok());
+        wait_for(&mut daemon, |daemon| daemon.services["sleep"].child.is_none());
+        daemon.tick(Instant::now());
+        assert_eq!(state(&mut daemon, "sleep"), "stopped");
+        assert_eq!(daemon.services["sleep"].exit_code, Some(128 + libc::SIGTERM));
+    }
+
+    #[test]
+    fn unknown_requests_are_rejected() {
+        let mut daemon = daemon("unknown", &[]);
+        assert!(daemon.handle("start missing", Instant::now()).is_err());
+        assert!(daemon.handle("reload missing", Instant::now()).is_err());
+        assert!(daemon.handle("start", Instant::now()).is_err());
+        assert!(daemon.handle("start ../services/x", Instant::now()).is_err());
+        assert!(daemon.handle("status ..", Instant::now()).is_err());
+        assert_eq!(state(&mut daemon, "missing"), "stopped");
+    }
+
+    #[test]
+    fn socket_is_private() {
+        let daemon = daemon("socket", &[]);
+        let socket = daemon.state_dir.socket();
+        let _listener = bind(&socket).unwrap();
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(UnixStream::connect(&socket).is_ok());
+        assert!(bind(&socket).is_err());
+        assert_eq!(std::fs::read_dir(&daemon.state_dir.0).unwrap().count(), 2);
+    }
+}
@@ -0,0 +1,75 @@
+// Escaping of values written to unit files, following systemd.syntax(7)
+
+use std::path::Path;
+
+/// Wraps a word in double quotes using the C-style escapes systemd unquotes
+pub fn quote(word: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in word.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '%' => quoted.push_str("%%"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_ascii_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Renders a path taking the rest of the line, such as `WorkingDirectory=`
+pub fn path(path: &Path) -> crate::Result<String> {
+    match path.to_str() {
+        Some(path) if !path.chars().any(char::is_control) => Ok(path.replace('%', "%%")),
+        _ => Err(crate::Error::new(crate::ErrorKind::Io, "Cannot decode path".to_string())
+            .content(path.display().to_string())),
+    }
+}
+
+/// Renders free text taking the rest of the line, such as `Description=` or `User=`
+pub fn text(what: &str, value: &str) -> crate::Result<String> {
+    if value.chars().any(char::is_control) {
+        return Err(crate::Error::new(crate::ErrorKind::Other,
+                                     format!("Control characters are not allowed in {}", what))
+            .content(format!("{:?}", value)));
+    }
+    Ok(value.replace('%', "%%"))
+}
+
+/// Renders a command line such as `ExecStart=`, one quoted word per argument
+pub fn command(executable: &Path, args: &[String]) -> crate::Result<String> {
+    let executable = match executable.to_str() {
+        Some(executable) => executable,
+        None => return Err(crate::Error::new(crate::ErrorKind::Io,
+                                             "Cannot decode path".to_string())
+            .content(executable.display().to_string())),
+    };
+
+    let mut words = vec![];
+    for word in std::iter::once(executable).chain(args.iter().map(String::as_str)) {
+        if word.chars().any(char::is_control) {
+            return Err(crate::Error::new(crate::ErrorKind::Other,
+                                         "Control characters are not allowed in a command"
+                                             .to_string())
+                .content(format!("{:?}", word)));
+        }
+        // A lone semicolon separates commands, even when quoted
+        if word == ";" {
+            words.push("\\;".to_string());
+        } else {
+            // Variables are expanded inside quotes too
+            words.push(quote(word).replace('$', "$$"));
+        }
+    }
+    Ok(words.join(" "))
+}
+
+pub fn env_assignment(key: &str, value: &str) -> crate::Result<String> {
+    crate::env::check_name(key)?;
+    Ok(quote(&format!("{}={}", key, value)))
+}
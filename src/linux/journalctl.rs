@@ -0,0 +1,161 @@
+use crate::{LogLine, LogQuery, Logs, Priority, Scope};
+use crate::linux::tool;
+use std::io::{BufRead, BufReader, Lines, Read};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+fn unit(scope: Scope, name: &str) -> String {
+    match scope {
+        Scope::System => format!("--unit={}.service", name),
+        Scope::User => format!("--user-unit={}.service", name),
+    }
+}
+
+fn epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Messages the service logged to the journal since the given time, one per line
+pub fn messages_since(program: &Path, scope: Scope, name: &str, since: SystemTime)
+                      -> crate::Result<String> {
+    let unit = unit(scope, name);
+    let since = format!("--since=@{}", epoch(since));
+    tool::run(program, "journalctl", &[&unit, &since, "--output=cat", "--no-pager", "--quiet"])?
+        .check()
+}
+
+/// Journal entries of the service, read from `journalctl --output=json`
+pub fn logs(program: &Path, scope: Scope, name: &str, query: &LogQuery) -> crate::Result<Logs> {
+    let mut args = vec![unit(scope, name), "--output=json".to_string(), "--no-pager".to_string(),
+                        "--quiet".to_string()];
+    if let Some(lines) = query.lines {
+        args.push(format!("--lines={}", lines));
+    }
+    if let Some(since) = query.since {
+        args.push(format!("--since=@{}", epoch(since)));
+    }
+    if let Some(until) = query.until {
+        args.push(format!("--until=@{}", epoch(until)));
+    }
+    if let Some(priority) = query.priority {
+        args.push(format!("--priority={}", priority.level()));
+    }
+    if query.follow {
+        args.push("--follow".to_string());
+    }
+
+    let command_line = format!("journalctl {}", args.join(" "));
+    let mut child = Command::new(program)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| crate::Error::from(e).content(command_line.clone()))?;
+    let lines = match child.stdout.take() {
+        Some(stdout) => BufReader::new(stdout).lines(),
+        None => return Err(crate::Error::new(crate::ErrorKind::Io,
+                                             "Cannot read journalctl output".to_string())),
+    };
+
+    Ok(Logs::new(Journal {
+        child,
+        lines,
+        command_line,
+    }))
+}
+
+struct Journal {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+    command_line: String,
+}
+
+impl Iterator for Journal {
+    type Item = crate::Result<LogLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lines.next() {
+            Some(Ok(line)) => Some(parse_entry(&line)),
+            Some(Err(e)) => Some(Err(e.into())),
+            // Output ended, a failure of journalctl is only known now
+            None => match self.child.wait() {
+                Ok(status) if !status.success() => {
+                    let mut stderr = String::new();
+                    if let Some(mut pipe) = self.child.stderr.take() {
+                        let _ = pipe.read_to_string(&mut stderr);
+                    }
+                    let error = crate::Error::new(crate::ErrorKind::ServiceManager,
+                                                  stderr.trim().to_string())
+                        .content(self.command_line.clone());
+                    Some(Err(match status.code() {
+                        Some(code) => error.exit_code(code),
+                        None => error,
+                    }))
+                },
+                _ => None,
+            },
+        }
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        // A following journalctl never exits by itself
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn parse_entry(line: &str) -> crate::Result<LogLine> {
+    let invalid = || crate::Error::new(crate::ErrorKind::Other,
+                                       "Invalid journal entry".to_string())
+        .content(line.to_string());
+    let entry: serde_json::Value = serde_json::from_str(line).map_err(|_| invalid())?;
+
+    let timestamp = entry["__REALTIME_TIMESTAMP"].as_str()
+        .and_then(|usec| usec.parse().ok())
+        .map(|usec| UNIX_EPOCH + Duration::from_micros(usec))
+        .ok_or_else(invalid)?;
+    let priority = entry["PRIORITY"].as_str()
+        .and_then(|level| level.parse().ok())
+        .and_then(Priority::from_level);
+    let message = match &entry["MESSAGE"] {
+        serde_json::Value::String(message) => message.clone(),
+        // Messages that are not valid UTF-8 come as an array of bytes
+        serde_json::Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes.iter()
+                .filter_map(|byte| byte.as_u64().map(|byte| byte as u8))
+                .collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        },
+        _ => String::new(),
+    };
+
+    Ok(LogLine {
+        timestamp: Some(timestamp),
+        priority,
+        message,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_json_entries() {
+        let line = parse_entry(r#"{"__REALTIME_TIMESTAMP":"1600000000123456","PRIORITY":"3","MESSAGE":"Recv: <2>","_PID":"42"}"#);
+        assert_eq!(line.map_err(|e| e.to_string()), Ok(LogLine {
+            timestamp: Some(UNIX_EPOCH + Duration::from_micros(1_600_000_000_123_456)),
+            priority: Some(Priority::Error),
+            message: "Recv: <2>".to_string(),
+        }));
+
+        let line = parse_entry(r#"{"__REALTIME_TIMESTAMP":"1","MESSAGE":[104,105,255]}"#);
+        assert_eq!(line.map(|line| (line.priority, line.message)),
+                   Ok((None, "hi\u{fffd}".to_string())));
+        assert!(parse_entry("not json").is_err());
+    }
+}
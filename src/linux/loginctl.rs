@@ -0,0 +1,7 @@
+use crate::linux::tool;
+use std::path::Path;
+
+/// Lets the user manager of the current user run without an open session
+pub fn enable_linger() -> crate::Result<()> {
+    tool::run(Path::new("loginctl"), "loginctl", &["enable-linger"])?.check().map(|_| ())
+}
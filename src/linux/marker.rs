@@ -0,0 +1,37 @@
+use crate::marker::Marker;
+
+// systemd ignores sections prefixed with "X-", so the marker lives inside the unit itself
+const SECTION: &str = "[X-Sombra]";
+
+pub fn render(marker: &Marker) -> String {
+    let mut section = format!("{}\n", SECTION);
+    for (key, value) in marker.entries() {
+        section.push_str(&format!("{}={}\n", key, value));
+    }
+    section
+}
+
+pub fn parse(name: &str, unit: &str) -> Option<Marker> {
+    let entries = unit.lines()
+        .skip_while(|line| line.trim() != SECTION)
+        .skip(1)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| {
+            let mut pair = line.splitn(2, '=');
+            Some((pair.next()?, pair.next()?))
+        });
+    Marker::from_entries(name, entries)
+}
+
+/// The same section as comments, for the shell scripts of the other init systems
+pub fn render_comment(marker: &Marker) -> String {
+    render(marker).lines().map(|line| format!("# {}\n", line)).collect()
+}
+
+pub fn parse_comment(name: &str, script: &str) -> Option<Marker> {
+    let section: String = script.lines()
+        .filter_map(|line| line.strip_prefix("# "))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    parse(name, &section)
+}
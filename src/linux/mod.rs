@@ -1,2 +1,27 @@
 pub mod sombra_imp;
+mod supervisor;
+mod openrc;
+mod sysv;
+mod servicedir;
+pub mod daemon;
+pub mod service;
 mod systemctl;
+mod tool;
+mod marker;
+mod escape;
+mod account;
+mod journalctl;
+mod loginctl;
+mod shell;
+
+/// Variable of the environment the CLI tests redirect the binary with, read only by builds with
+/// the `test-hooks` feature, so the caller of `sudo -E` cannot pick what root runs or writes
+#[cfg(feature = "test-hooks")]
+fn test_hook(name: &str) -> Option<std::path::PathBuf> {
+    std::env::var_os(name).filter(|value| !value.is_empty()).map(std::path::PathBuf::from)
+}
+
+#[cfg(not(feature = "test-hooks"))]
+fn test_hook(_: &str) -> Option<std::path::PathBuf> {
+    None
+}
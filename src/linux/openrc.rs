@@ -0,0 +1,580 @@
+use crate::{Sombra, LogFiles, LogQuery, Logs, Readiness, RestartMode, RestartPolicy, Scope,
+            ServiceSpec,
+            ServiceState, ServiceStatus, StartupMode};
+use crate::linux::account;
+use crate::linux::marker;
+use crate::linux::shell;
+use crate::linux::tool;
+use crate::marker::Marker;
+use crate::spec::WorkingDirectory;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const INIT_DIR: &str = "/etc/init.d";
+const RUNLEVEL: &str = "default";
+const LOG_DIR: &str = "/var/log";
+
+/// Service of OpenRC, run by an `/etc/init.d/<name>` script
+pub struct SombraOpenRC {
+    spec: ServiceSpec,
+    init_dir: PathBuf,
+}
+
+impl SombraOpenRC {
+    fn script_content(marker: &Marker) -> crate::Result<String> {
+        let spec = &marker.spec;
+        let description = match &spec.description {
+            Some(description) => description.clone(),
+            None => format!("{} service", spec.name),
+        };
+        let mut lines = vec![format!("description={}", shell::quote(&description))];
+        let user = match &spec.user {
+            Some(user) => user.clone(),
+            None => whoami::username(),
+        };
+        // supervise-daemon respawns after any exit, the wrapper stops it after the others
+        match exit_wrapper(&spec.name, &spec.restart) {
+            Some(wrapper) => {
+                let executable = spec.executable.to_str()
+                    .ok_or_else(|| crate::Error::new(crate::ErrorKind::Io,
+                                                     "Cannot decode path".to_string())
+                        .content(spec.executable.display().to_string()))?;
+                let mut words = vec!["-c", &wrapper];
+                // The wrapper stays root to call rc-service, su switches the service itself
+                if user != "root" {
+                    words.extend(["su", "-s", "/bin/sh", "-c", "exec \"$0\" \"$@\"", "--", &user]);
+                }
+                words.push(executable);
+                words.extend(spec.args.iter().map(String::as_str));
+                lines.push("command=/bin/sh".to_string());
+                lines.push(format!("command_args={}", shell::eval_words(&words)));
+            },
+            None => {
+                // openrc-run expands the command unquoted, inside `eval`
+                let command = spec.executable.to_str()
+                    .filter(|command| shell::is_plain(command))
+                    .ok_or_else(|| crate::Error::new(crate::ErrorKind::Unsupported,
+                                                     "OpenRC cannot run an executable whose \
+                                                      path needs quoting".to_string())
+                        .content(spec.executable.display().to_string()))?;
+                lines.push(format!("command={}", command));
+                lines.push(format!("command_args={}", shell::eval_words(&spec.args)));
+                let account = match &spec.group {
+                    Some(group) => format!("{}:{}", user, group),
+                    None => user,
+                };
+                lines.push(format!("command_user={}", shell::eval_words(&[account])));
+            },
+        }
+        if let Some(WorkingDirectory::Path(path)) = &spec.working_directory {
+            lines.push(format!("directory={}", eval_path(path)?));
+        }
+        let log_files = SombraOpenRC::log_files(spec);
+        lines.push(format!("output_log={}", eval_path(&log_files.stdout)?));
+        lines.push(format!("error_log={}",
+                           eval_path(log_files.stderr.as_ref().unwrap_or(&log_files.stdout))?));
+
+        lines.push("supervisor=supervise-daemon".to_string());
+        if let Some(delay) = spec.restart.delay {
+            lines.push(format!("respawn_delay={}", seconds(delay)));
+        }
+        match spec.restart.burst {
+            Some((count, interval)) => {
+                lines.push(format!("respawn_max={}", count));
+                lines.push(format!("respawn_period={}", seconds(interval)));
+            },
+            None => lines.push("respawn_max=0".to_string()),
+        }
+        for (key, value) in spec.environment()? {
+            lines.push(shell::export(&key, &value)?);
+        }
+
+        Ok(format!("#!/sbin/openrc-run\n\
+                    \n\
+                    {}\n\
+                    \n\
+                    depend() {{\n\
+                    \tafter net\n\
+                    }}\n\
+                    \n\
+                    {}",
+                   lines.join("\n"),
+                   marker::render_comment(marker)))
+    }
+
+    // OpenRC has no journal, output goes to `/var/log/<name>.log` unless the spec says otherwise
+    fn log_files(spec: &ServiceSpec) -> LogFiles {
+        match &spec.log_files {
+            Some(log_files) => log_files.clone(),
+            None => LogFiles::new(&format!("{}/{}.log", LOG_DIR, spec.name)),
+        }
+    }
+
+    fn parse_status(stdout: &str) -> ServiceState {
+        let state = stdout.split("status: ").nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .unwrap_or("");
+        match state {
+            "started" | "starting" | "stopping" => ServiceState::Running,
+            "crashed" => ServiceState::Failed,
+            _ => ServiceState::Stopped,
+        }
+    }
+
+    fn script_path(&self) -> PathBuf {
+        self.init_dir.join(&self.spec.name)
+    }
+
+    fn rc_service(&self, action: &str) -> crate::Result<String> {
+        tool::run(Path::new("rc-service"), "rc-service", &[&self.spec.name, action])?.check()
+    }
+
+    fn rc_update(&self, action: &str) -> crate::Result<()> {
+        tool::run(Path::new("rc-update"), "rc-update", &[action, &self.spec.name, RUNLEVEL])?
+            .check()
+            .map(|_| ())
+    }
+
+    // Spec the service was created from, as recorded in its script
+    fn stored_spec(&self) -> crate::Result<Option<ServiceSpec>> {
+        let script = std::fs::read_to_string(self.script_path())?;
+        Ok(marker::parse_comment(&self.spec.name, &script).map(|marker| marker.spec))
+    }
+
+    // Starts the service, then waits for the readiness probe reading the output files
+    fn launch(&self, readiness: Option<&Readiness>, log_files: &LogFiles) -> crate::Result<()> {
+        let sizes = log_files.prepare()?;
+        self.rc_service("start")?;
+        match readiness {
+            Some(readiness) => readiness.wait(&self.spec.name, || log_files.output_since(&sizes)),
+            None => Ok(()),
+        }
+    }
+
+    fn remove(&self, force: bool) -> crate::Result<()> {
+        self.check_privileges("Deleting")?;
+        if !force && self.stored_spec()?.is_none() {
+            return Err(crate::Error::new(crate::ErrorKind::NotManaged,
+                                         format!("Service {} was not created by sombra",
+                                                 self.spec.name)));
+        }
+        let _ = self.rc_service("stop");
+        // Fails when the service is in no runlevel
+        let _ = self.rc_update("del");
+        Ok(std::fs::remove_file(self.script_path())?)
+    }
+
+    fn check_exists(&self) -> crate::Result<()> {
+        if self.script_path().exists() {
+            Ok(())
+        } else {
+            Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} does not exist",
+                                                                self.spec.name)))
+        }
+    }
+
+    fn check_privileges(&self, operation: &str) -> crate::Result<()> {
+        if account::is_privileged(&self.init_dir) {
+            return Ok(());
+        }
+        Err(crate::Error::new(crate::ErrorKind::PermissionDenied,
+                              format!("{} service {} needs root privileges",
+                                      operation, self.spec.name))
+            .content(self.init_dir.display().to_string()))
+    }
+}
+
+// A path the init script passes through `eval`
+fn eval_path(path: &Path) -> crate::Result<String> {
+    match path.to_str() {
+        Some(path) => Ok(shell::eval_words(&[path])),
+        None => Err(crate::Error::new(crate::ErrorKind::Io, "Cannot decode path".to_string())
+            .content(path.display().to_string())),
+    }
+}
+
+// Script supervise-daemon runs in place of the service, for exits the policy does not
+// restart. After a clean exit it stops the service through rc-service, so OpenRC reports it
+// stopped, after the others it kills the supervisor (its parent), which OpenRC reports as
+// crashed. Signals show as exit codes past 128. None when every exit restarts.
+fn exit_wrapper(name: &str, restart: &RestartPolicy) -> Option<String> {
+    let mut clean = vec![0];
+    clean.extend(&restart.success_exit_codes);
+    let prevented = &restart.prevent_exit_codes;
+    let (stopped, crashed): (Vec<i32>, Vec<String>) = match restart.mode {
+        RestartMode::Always => (clean.iter().copied().filter(|code| prevented.contains(code))
+                                    .collect(),
+                                prevented.iter().filter(|code| !clean.contains(code))
+                                    .map(|code| code.to_string())
+                                    .collect()),
+        RestartMode::OnFailure => (clean, prevented.iter().map(|code| code.to_string()).collect()),
+        RestartMode::Never | RestartMode::OnAbnormal => (clean, vec!["*".to_string()]),
+    };
+    let mut arms = vec![];
+    if !stopped.is_empty() {
+        let codes: Vec<String> = stopped.iter().map(|code| code.to_string()).collect();
+        arms.push(format!("{}) {{ rc-service {} stop || kill \"$PPID\"; }} >/dev/null 2>&1 & \
+                           wait $! ;;", codes.join("|"), shell::quote(name)));
+    }
+    if restart.mode == RestartMode::OnAbnormal {
+        arms.push("129|1[3-9][0-9]|2[0-5][0-9]) ;;".to_string());
+    }
+    if !crashed.is_empty() {
+        arms.push(format!("{}) kill \"$PPID\" ;;", crashed.join("|")));
+    }
+    if arms.is_empty() {
+        return None;
+    }
+    Some(format!("trap 'kill \"$pid\"; wait \"$pid\"; exit' TERM INT; \"$0\" \"$@\" & pid=$!; \
+                  wait \"$pid\"; code=$?; case $code in {} esac; exit $code",
+                 arms.join(" ")))
+}
+
+// OpenRC counts in whole seconds, rounding up keeps short delays from vanishing
+fn seconds(duration: Duration) -> u64 {
+    duration.as_millis().div_ceil(1000) as u64
+}
+
+fn unsupported(what: &str) -> crate::Error {
+    crate::Error::new(crate::ErrorKind::Unsupported, format!("{} not supported by OpenRC", what))
+}
+
+impl Sombra for SombraOpenRC {
+    fn from_spec(spec: ServiceSpec) -> crate::Result<Self> {
+        let spec = spec.canonicalize()?;
+        if spec.scope == Scope::User {
+            return Err(unsupported("User services are"));
+        }
+        if spec.root.is_some() {
+            return Err(unsupported("Installing into a root directory is"));
+        }
+        if spec.linger {
+            return Err(crate::Error::new(crate::ErrorKind::Other,
+                                         "Lingering only applies to user services".to_string()));
+        }
+        if !spec.supplementary_groups.is_empty() || !spec.ambient_capabilities.is_empty() {
+            return Err(unsupported("Supplementary groups and capabilities are"));
+        }
+        if spec.restart.backoff.is_some() {
+            return Err(unsupported("Restart backoff is"));
+        }
+        // Exit codes cannot tell a signal from an exit past 128
+        if spec.restart.mode == RestartMode::OnAbnormal
+            && !spec.restart.prevent_exit_codes.is_empty() {
+            return Err(unsupported("Exit codes preventing an on-abnormal restart are"));
+        }
+        // su switches to the account of the user, with its primary group
+        if spec.group.is_some() && exit_wrapper(&spec.name, &spec.restart).is_some() {
+            return Err(unsupported("Groups of services not restarted after every exit are"));
+        }
+        if let Some(user) = &spec.user {
+            account::user(user)?;
+        }
+        if let Some(group) = &spec.group {
+            account::group(group)?;
+        }
+
+        Ok(SombraOpenRC {
+            spec,
+            init_dir: PathBuf::from(INIT_DIR),
+        })
+    }
+
+    fn list_in(scope: Scope) -> crate::Result<Vec<Self>> {
+        if scope == Scope::User {
+            return Err(unsupported("User services are"));
+        }
+        let mut services = vec![];
+        for entry in std::fs::read_dir(INIT_DIR)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let marker = match std::fs::read_to_string(&path).ok()
+                .and_then(|script| marker::parse_comment(&name, &script)) {
+                Some(marker) => marker,
+                None => continue,
+            };
+            services.push(SombraOpenRC {
+                spec: marker.spec,
+                init_dir: PathBuf::from(INIT_DIR),
+            });
+        }
+        services.sort_by(|a, b| a.spec.name.cmp(&b.spec.name));
+        Ok(services)
+    }
+
+    fn spec(&self) -> &ServiceSpec {
+        &self.spec
+    }
+
+    fn create(&self) -> crate::Result<()> {
+        self.check_privileges("Creating")?;
+
+        let path = self.script_path();
+        if path.exists() {
+            return Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} already exist",
+                                         self.spec.name)));
+        }
+        let script = SombraOpenRC::script_content(&Marker::new(&self.spec))?;
+        std::fs::create_dir_all(&self.init_dir)?;
+        std::fs::write(&path, script)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+
+        match self.spec.startup {
+            StartupMode::Disabled => return Ok(()),
+            StartupMode::Boot => self.rc_update("add")?,
+            StartupMode::Manual => (),
+        }
+        self.launch(self.spec.readiness.as_ref(), &SombraOpenRC::log_files(&self.spec))?;
+        if self.spec.readiness.is_some() {
+            return Ok(());
+        }
+
+        // Without a probe, give the process a moment to fail
+        std::thread::sleep(Duration::from_millis(100));
+        let status = self.status()?;
+        if !status.is_running() {
+            return Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                         format!("Service {} is {} after start, expected running",
+                                                 self.spec.name, status.state))
+                .content(format!("rc-service {} status", self.spec.name)));
+        }
+        Ok(())
+    }
+
+    fn delete(&self) -> crate::Result<()> {
+        self.remove(false)
+    }
+
+    fn force_delete(&self) -> crate::Result<()> {
+        self.remove(true)
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        self.check_privileges("Starting")?;
+        self.check_exists()?;
+        let stored = self.stored_spec()?.unwrap_or_else(|| self.spec.clone());
+        let readiness = match &self.spec.readiness {
+            Some(readiness) => Some(readiness.clone()),
+            None => stored.readiness.clone(),
+        };
+        self.launch(readiness.as_ref(), &SombraOpenRC::log_files(&stored))
+    }
+
+    fn stop(&self) -> crate::Result<()> {
+        self.check_privileges("Stopping")?;
+        self.check_exists()?;
+        self.rc_service("stop").map(|_| ())
+    }
+
+    fn restart(&self) -> crate::Result<()> {
+        self.check_privileges("Restarting")?;
+        self.check_exists()?;
+        self.rc_service("restart").map(|_| ())
+    }
+
+    fn enable(&self) -> crate::Result<()> {
+        self.check_privileges("Enabling")?;
+        self.check_exists()?;
+        self.rc_update("add")
+    }
+
+    fn disable(&self) -> crate::Result<()> {
+        self.check_privileges("Disabling")?;
+        self.check_exists()?;
+        self.rc_update("del")
+    }
+
+    fn status(&self) -> crate::Result<ServiceStatus> {
+        if !self.script_path().exists() {
+            return Ok(ServiceStatus::new(ServiceState::NotInstalled));
+        }
+        // status exits with 3 for a stopped service and 32 for a crashed one
+        let output = tool::run(Path::new("rc-service"), "rc-service", &[&self.spec.name, "status"])?;
+        let mut status = ServiceStatus::new(SombraOpenRC::parse_status(&output.stdout));
+        if status.is_running() {
+            status.health = crate::health::load(&crate::health::health_dir(self.spec.scope),
+                                                &self.spec.name);
+        }
+        Ok(status)
+    }
+
+    fn logs(&self, query: &LogQuery) -> crate::Result<Logs> {
+        let stored = match &self.spec.log_files {
+            Some(_) => None,
+            None => self.stored_spec().ok().flatten(),
+        };
+        SombraOpenRC::log_files(stored.as_ref().unwrap_or(&self.spec)).logs(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(spec: ServiceSpec) -> String {
+        let spec = spec.canonicalize().unwrap();
+        SombraOpenRC::script_content(&Marker::new(&spec)).unwrap()
+    }
+
+    #[test]
+    fn script_uses_supervise_daemon() {
+        let script = script(ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .args(vec!["-p".to_string(), "30222".to_string()])
+            .user("root")
+            .env("GREETING", "it's $HOME")
+            .restart(RestartPolicy::new(RestartMode::OnFailure)
+                .delay(Duration::from_millis(1500))
+                .burst(5, Duration::from_secs(600))));
+        let lines: Vec<_> = script.lines().collect();
+        assert_eq!(lines[0], "#!/sbin/openrc-run");
+        assert!(lines.iter().any(|line| line.starts_with("command_args=")
+            && line.ends_with("/executables/tcp_echo' '-p' '30222'\"")));
+        assert!(!script.contains("command_user="));
+        assert!(!script.contains("'su'"));
+        assert!(lines.contains(&"output_log=\"'/var/log/tcp_echo.log'\""));
+        assert!(lines.contains(&"supervisor=supervise-daemon"));
+        assert!(lines.contains(&"respawn_delay=2"));
+        assert!(lines.contains(&"respawn_max=5"));
+        assert!(lines.contains(&"respawn_period=600"));
+        assert!(lines.contains(&"export GREETING='it'\\''s $HOME'"));
+
+        let marker = marker::parse_comment("tcp_echo", &script).unwrap();
+        assert_eq!(marker.spec.args, vec!["-p", "30222"]);
+        assert_eq!(marker.spec.user, Some("root".to_string()));
+    }
+
+    #[test]
+    fn never_restarting_script_is_supervised() {
+        let never = script(ServiceSpec::new("tcp_echo", "executables/tcp_echo"));
+        assert!(never.contains("\nsupervisor=supervise-daemon\n"));
+        assert!(never.contains("\ncommand=/bin/sh\n"));
+        assert!(!never.contains("command_background"));
+        let nobody = script(ServiceSpec::new("tcp_echo", "executables/tcp_echo").user("nobody"));
+        assert!(nobody.contains("'su' '-s' '/bin/sh' '-c' 'exec \\\"\\$0\\\" \\\"\\$@\\\"' \
+                                 '--' 'nobody' '/"));
+        assert!(!nobody.contains("command_user="));
+        let always = script(ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .user("nobody")
+            .restart(RestartPolicy::new(RestartMode::Always)));
+        assert!(always.contains("/executables/tcp_echo\n"));
+        assert!(always.contains("\ncommand_user=\"'nobody'\"\n"));
+
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo").group("root");
+        let error = SombraOpenRC::from_spec(spec).err().unwrap();
+        assert_eq!(error.kind(), &crate::ErrorKind::Unsupported);
+    }
+
+    // Directory holding a stand-in for rc-service, removed even if the test panics. Its stop
+    // ends the supervisor as `supervise-daemon --stop` does, its status reports what OpenRC
+    // would: stopped after a stop, started while the supervisor lives on, crashed otherwise
+    struct FakeOpenRC {
+        dir: PathBuf,
+    }
+
+    impl FakeOpenRC {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("sombra-openrc-{}-{}", test, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let rc_service = dir.join("rc-service");
+            std::fs::write(&rc_service, "#!/bin/sh\n\
+                                         dir=$(dirname \"$0\")\n\
+                                         case \"$2\" in\n\
+                                         stop) touch \"$dir/stopped\"; \
+                                               kill \"$(cat \"$dir/supervisor\")\" ;;\n\
+                                         status) if [ -f \"$dir/stopped\" ]; then \
+                                                     echo ' * status: stopped'; \
+                                                 elif [ -f \"$dir/respawned\" ]; then \
+                                                     echo ' * status: started'; \
+                                                 else \
+                                                     echo ' * status: crashed'; \
+                                                 fi ;;\n\
+                                         esac\n").unwrap();
+            std::fs::set_permissions(&rc_service, std::fs::Permissions::from_mode(0o755))
+                .unwrap();
+            FakeOpenRC { dir }
+        }
+
+        // Runs `command` through the wrapper under a shell standing in for supervise-daemon,
+        // then reads the state of the service as OpenRC reports it
+        fn state_after(&self, restart: &RestartPolicy, command: &str) -> ServiceState {
+            let _ = std::fs::remove_file(self.dir.join("stopped"));
+            let _ = std::fs::remove_file(self.dir.join("respawned"));
+            let wrapper = exit_wrapper("tcp_echo", restart).unwrap();
+            std::process::Command::new("/bin/sh")
+                .arg("-c")
+                .arg("echo $$ > \"$2/supervisor\"; /bin/sh -c \"$0\" /bin/sh -c \"$1\"; \
+                      touch \"$2/respawned\"")
+                .arg(wrapper)
+                .arg(command)
+                .arg(&self.dir)
+                .env("PATH", format!("{}:/usr/bin:/bin", self.dir.display()))
+                .output()
+                .unwrap();
+            let status = std::process::Command::new(self.dir.join("rc-service"))
+                .args(["tcp_echo", "status"])
+                .output()
+                .unwrap();
+            SombraOpenRC::parse_status(&String::from_utf8_lossy(&status.stdout))
+        }
+    }
+
+    impl Drop for FakeOpenRC {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn clean_exit_is_stopped() {
+        let openrc = FakeOpenRC::new("clean");
+        let never = RestartPolicy::new(RestartMode::Never).success_exit_code(3);
+        assert_eq!(openrc.state_after(&never, "exit 0"), ServiceState::Stopped);
+        assert_eq!(openrc.state_after(&never, "exit 3"), ServiceState::Stopped);
+        assert_eq!(openrc.state_after(&never, "exit 1"), ServiceState::Failed);
+    }
+
+    #[test]
+    fn wrapper_follows_restart_policy() {
+        let openrc = FakeOpenRC::new("policy");
+        let on_failure = RestartPolicy::new(RestartMode::OnFailure)
+            .success_exit_code(3)
+            .prevent_exit_code(4);
+        assert_eq!(openrc.state_after(&on_failure, "exit 1"), ServiceState::Running);
+        assert_eq!(openrc.state_after(&on_failure, "exit 0"), ServiceState::Stopped);
+        assert_eq!(openrc.state_after(&on_failure, "exit 3"), ServiceState::Stopped);
+        assert_eq!(openrc.state_after(&on_failure, "exit 4"), ServiceState::Failed);
+        let on_abnormal = RestartPolicy::new(RestartMode::OnAbnormal);
+        assert_eq!(openrc.state_after(&on_abnormal, "kill -TERM $$"), ServiceState::Running);
+        assert_eq!(openrc.state_after(&on_abnormal, "exit 0"), ServiceState::Stopped);
+        assert_eq!(openrc.state_after(&on_abnormal, "exit 1"), ServiceState::Failed);
+        let always = RestartPolicy::new(RestartMode::Always).prevent_exit_code(4);
+        assert_eq!(openrc.state_after(&always, "exit 0"), ServiceState::Running);
+        assert_eq!(openrc.state_after(&always, "exit 4"), ServiceState::Failed);
+        assert_eq!(exit_wrapper("tcp_echo", &RestartPolicy::new(RestartMode::Always)), None);
+    }
+
+    #[test]
+    fn marker_keeps_line_breaks() {
+        let script = script(ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .description("Echo\nserver")
+            .arg("x\nReadiness=file /tmp/ready"));
+        let marker = marker::parse_comment("tcp_echo", &script).unwrap();
+        assert_eq!(marker.spec.description, Some("Echo\nserver".to_string()));
+        assert_eq!(marker.spec.args, vec!["x\nReadiness=file /tmp/ready"]);
+        assert_eq!(marker.spec.readiness, None);
+    }
+
+    #[test]
+    fn parse_status_output() {
+        assert_eq!(SombraOpenRC::parse_status(" * status: started\n"), ServiceState::Running);
+        assert_eq!(SombraOpenRC::parse_status(" * status: crashed\n"), ServiceState::Failed);
+        assert_eq!(SombraOpenRC::parse_status(" * status: stopped\n"), ServiceState::Stopped);
+        assert_eq!(SombraOpenRC::parse_status(""), ServiceState::Stopped);
+    }
+}
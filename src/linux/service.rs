@@ -0,0 +1,118 @@
+use crate::{Sombra, Backend, LogQuery, Logs, Scope, ServiceSpec, ServiceStatus};
+use crate::linux::sombra_imp::SombraLinux;
+use crate::linux::openrc::SombraOpenRC;
+use crate::linux::sysv::SombraSysV;
+use crate::linux::servicedir::{SombraServiceDir, Supervision};
+use crate::linux::supervisor::SombraSupervisor;
+
+/// Service of the backend picked by `Backend::detect`
+pub enum Service {
+    Systemd(SombraLinux),
+    OpenRC(SombraOpenRC),
+    SysV(SombraSysV),
+    /// runit or s6
+    Supervised(SombraServiceDir),
+    Supervisor(SombraSupervisor),
+}
+
+macro_rules! dispatch {
+    ($service:expr, $backend:ident => $call:expr) => {
+        match $service {
+            Service::Systemd($backend) => $call,
+            Service::OpenRC($backend) => $call,
+            Service::SysV($backend) => $call,
+            Service::Supervised($backend) => $call,
+            Service::Supervisor($backend) => $call,
+        }
+    };
+}
+
+impl Sombra for Service {
+    fn from_spec(spec: ServiceSpec) -> crate::Result<Self> {
+        let backend = match Backend::from_env()? {
+            Some(backend) => backend,
+            // A root directory is the image of another system, which boots with systemd
+            None if spec.root.is_some() => Backend::Systemd,
+            None => Backend::detect()?,
+        };
+        Ok(match backend {
+            Backend::Systemd => Service::Systemd(SombraLinux::from_spec(spec)?),
+            Backend::OpenRC => Service::OpenRC(SombraOpenRC::from_spec(spec)?),
+            Backend::SysV => Service::SysV(SombraSysV::from_spec(spec)?),
+            Backend::Runit => Service::Supervised(
+                SombraServiceDir::with_supervision(spec, Supervision::Runit)?),
+            Backend::S6 => Service::Supervised(
+                SombraServiceDir::with_supervision(spec, Supervision::S6)?),
+            Backend::Supervisor => Service::Supervisor(SombraSupervisor::from_spec(spec)?),
+        })
+    }
+
+    fn list_in(scope: Scope) -> crate::Result<Vec<Self>> {
+        Ok(match Backend::detect()? {
+            Backend::Systemd => SombraLinux::list_in(scope)?.into_iter()
+                .map(Service::Systemd)
+                .collect(),
+            Backend::OpenRC => SombraOpenRC::list_in(scope)?.into_iter()
+                .map(Service::OpenRC)
+                .collect(),
+            Backend::SysV => SombraSysV::list_in(scope)?.into_iter()
+                .map(Service::SysV)
+                .collect(),
+            Backend::Runit => SombraServiceDir::list_supervised(scope, Supervision::Runit)?
+                .into_iter()
+                .map(Service::Supervised)
+                .collect(),
+            Backend::S6 => SombraServiceDir::list_supervised(scope, Supervision::S6)?
+                .into_iter()
+                .map(Service::Supervised)
+                .collect(),
+            Backend::Supervisor => SombraSupervisor::list_in(scope)?.into_iter()
+                .map(Service::Supervisor)
+                .collect(),
+        })
+    }
+
+    fn spec(&self) -> &ServiceSpec {
+        dispatch!(self, service => service.spec())
+    }
+
+    fn create(&self) -> crate::Result<()> {
+        dispatch!(self, service => service.create())
+    }
+
+    fn delete(&self) -> crate::Result<()> {
+        dispatch!(self, service => service.delete())
+    }
+
+    fn force_delete(&self) -> crate::Result<()> {
+        dispatch!(self, service => service.force_delete())
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        dispatch!(self, service => service.start())
+    }
+
+    fn stop(&self) -> crate::Result<()> {
+        dispatch!(self, service => service.stop())
+    }
+
+    fn restart(&self) -> crate::Result<()> {
+        dispatch!(self, service => service.restart())
+    }
+
+    fn enable(&self) -> crate::Result<()> {
+        dispatch!(self, service => service.enable())
+    }
+
+    fn disable(&self) -> crate::Result<()> {
+        dispatch!(self, service => service.disable())
+    }
+
+    fn status(&self) -> crate::Result<ServiceStatus> {
+        dispatch!(self, service => service.status())
+    }
+
+    fn logs(&self, query: &LogQuery) -> crate::Result<Logs> {
+        dispatch!(self, service => service.logs(query))
+    }
+}
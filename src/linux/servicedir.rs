@@ -0,0 +1,788 @@
+use crate::{Sombra, Backend, FileMode, LogFiles, LogQuery, Logs, Readiness, RestartMode, Scope,
+            ServiceSpec, ServiceState, ServiceStatus, StartupMode};
+use crate::linux::account;
+use crate::linux::marker;
+use crate::linux::shell;
+use crate::linux::supervisor::STOP_TIMEOUT;
+use crate::linux::tool;
+use crate::marker::Marker;
+use crate::spec::WorkingDirectory;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const LOG_DIR: &str = "/var/log";
+// Name of the file the loggers write to, older output is rotated next to it
+const CURRENT: &str = "current";
+// Time for runsvdir or s6-svscan to start supervising a new directory
+const SCAN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Supervision suite running the service directories
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Supervision {
+    Runit,
+    S6,
+}
+
+impl Supervision {
+    // Directory holding the service directories, and the directory scanned by the supervisor
+    fn dirs(self) -> (PathBuf, PathBuf) {
+        let sv_dir = PathBuf::from(match self {
+            Supervision::Runit => "/etc/sv",
+            Supervision::S6 => "/etc/s6/sv",
+        });
+        // Void links /var/service to the current runsvdir, Debian scans /etc/service
+        let scan_dir = PathBuf::from(match self {
+            Supervision::Runit if Path::new("/var/service").is_dir() => "/var/service",
+            Supervision::Runit => "/etc/service",
+            Supervision::S6 => "/run/service",
+        });
+        (sv_dir, scan_dir)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Supervision::Runit => "runit",
+            Supervision::S6 => "s6",
+        }
+    }
+
+    // Command run from the finish script, which runs in the service directory
+    fn down_command(self) -> &'static str {
+        match self {
+            Supervision::Runit => "sv down .",
+            Supervision::S6 => "s6-svc -d .",
+        }
+    }
+}
+
+/// Service of runit or s6, run from a service directory linked into the scan directory
+pub struct SombraServiceDir {
+    spec: ServiceSpec,
+    supervision: Supervision,
+    sv_dir: PathBuf,
+    scan_dir: PathBuf,
+}
+
+impl SombraServiceDir {
+    pub fn with_supervision(spec: ServiceSpec, supervision: Supervision) -> crate::Result<Self> {
+        let spec = spec.canonicalize()?;
+        let unsupported = |what: &str| unsupported(supervision, what);
+        if spec.scope == Scope::User {
+            return Err(unsupported("User services are"));
+        }
+        if spec.root.is_some() {
+            return Err(unsupported("Installing into a root directory is"));
+        }
+        if spec.linger {
+            return Err(crate::Error::new(crate::ErrorKind::Other,
+                                         "Lingering only applies to user services".to_string()));
+        }
+        if !spec.supplementary_groups.is_empty() || !spec.ambient_capabilities.is_empty() {
+            return Err(unsupported("Supplementary groups and capabilities are"));
+        }
+        if spec.restart.burst.is_some() || spec.restart.backoff.is_some() {
+            return Err(unsupported("Restart bursts and backoff are"));
+        }
+        // Every exit code already keeps an on-abnormal service down
+        if spec.restart.mode == RestartMode::OnAbnormal
+            && !spec.restart.prevent_exit_codes.is_empty() {
+            return Err(unsupported("Exit codes preventing an on-abnormal restart are"));
+        }
+        if let Some(log_files) = &spec.log_files {
+            if log_files.stderr.is_some() || log_files.stdout.file_name() != Some(CURRENT.as_ref()) {
+                return Err(unsupported("Output files other than a single <dir>/current are"));
+            }
+        }
+        if let Some(user) = &spec.user {
+            account::user(user)?;
+        }
+        if let Some(group) = &spec.group {
+            account::group(group)?;
+        }
+
+        let (sv_dir, scan_dir) = supervision.dirs();
+        Ok(SombraServiceDir { spec, supervision, sv_dir, scan_dir })
+    }
+
+    pub fn list_supervised(scope: Scope, supervision: Supervision) -> crate::Result<Vec<Self>> {
+        if scope == Scope::User {
+            return Err(unsupported(supervision, "User services are"));
+        }
+        let (sv_dir, scan_dir) = supervision.dirs();
+        let mut services = vec![];
+        let entries = match std::fs::read_dir(&sv_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(services),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let marker = match std::fs::read_to_string(path.join("run")).ok()
+                .and_then(|script| marker::parse_comment(&name, &script)) {
+                Some(marker) => marker,
+                None => continue,
+            };
+            services.push(SombraServiceDir {
+                spec: marker.spec,
+                supervision,
+                sv_dir: sv_dir.clone(),
+                scan_dir: scan_dir.clone(),
+            });
+        }
+        services.sort_by(|a, b| a.spec.name.cmp(&b.spec.name));
+        Ok(services)
+    }
+
+    fn run_content(&self, marker: &Marker) -> crate::Result<String> {
+        let spec = &marker.spec;
+        let mut lines = vec!["exec 2>&1".to_string()];
+        for (key, value) in spec.environment()? {
+            lines.push(shell::export(&key, &value)?);
+        }
+        if let Some(WorkingDirectory::Path(path)) = &spec.working_directory {
+            lines.push(format!("cd {} || exit 1", quote_path(path)?));
+        }
+
+        let user = match &spec.user {
+            Some(user) => user.clone(),
+            None => whoami::username(),
+        };
+        let mut words = match self.supervision {
+            Supervision::Runit => {
+                let account = match &spec.group {
+                    Some(group) => format!("{}:{}", user, group),
+                    None => user,
+                };
+                vec!["chpst".to_string(), "-u".to_string(), shell::quote(&account)]
+            },
+            // s6-setuidgid takes a group only in numeric form
+            Supervision::S6 => {
+                let account = match &spec.group {
+                    Some(group) => format!("{}:{}", account::user(&user)?.uid,
+                                           account::group(group)?),
+                    None => user,
+                };
+                vec!["s6-setuidgid".to_string(), shell::quote(&account)]
+            },
+        };
+        words.push(quote_path(&spec.executable)?);
+        words.extend(spec.args.iter().map(|arg| shell::quote(arg)));
+        lines.push(format!("exec {}", words.join(" ")));
+
+        Ok(format!("#!/bin/sh\n{}\n\n{}", lines.join("\n"), marker::render_comment(marker)))
+    }
+
+    // The supervisor restarts `run` whenever it exits, `finish` marks the service down instead
+    // when the policy says so, or sleeps the restart delay
+    fn finish_content(&self) -> Option<String> {
+        let restart = &self.spec.restart;
+        let mut down_codes: Vec<String> = restart.prevent_exit_codes.iter()
+            .map(|code| code.to_string())
+            .collect();
+        let mut arms = vec![];
+        match restart.mode {
+            RestartMode::Never => down_codes = vec!["*".to_string()],
+            RestartMode::OnFailure => {
+                down_codes.push("0".to_string());
+                down_codes.extend(restart.success_exit_codes.iter().map(|code| code.to_string()));
+            },
+            // runit passes -1 and s6 passes 256 after a signal
+            RestartMode::OnAbnormal => {
+                arms.push("\t-1|256) ;;".to_string());
+                down_codes = vec!["*".to_string()];
+            },
+            RestartMode::Always => (),
+        }
+        if down_codes.is_empty() && restart.delay.is_none() {
+            return None;
+        }
+
+        let mut lines = vec!["#!/bin/sh".to_string()];
+        if !down_codes.is_empty() {
+            arms.push(format!("\t{}) exec {} ;;", down_codes.join("|"),
+                              self.supervision.down_command()));
+            lines.push(format!("case \"$1\" in\n{}\nesac", arms.join("\n")));
+        }
+        if let Some(delay) = restart.delay {
+            lines.push(format!("exec sleep {}", seconds(delay)));
+        }
+        Some(format!("{}\n", lines.join("\n")))
+    }
+
+    fn log_run_content(&self) -> crate::Result<String> {
+        let log_files = SombraServiceDir::log_files(&self.spec);
+        let dir = quote_path(log_dir(&log_files))?;
+        Ok(match (self.supervision, log_files.max_size) {
+            // svlogd reads its rotation settings from a config file in the directory
+            (Supervision::Runit, _) => format!("#!/bin/sh\nexec svlogd {}\n", dir),
+            (Supervision::S6, Some(max_size)) => format!("#!/bin/sh\nexec s6-log -b n{} s{} {}\n",
+                                                         log_files.retain, max_size, dir),
+            (Supervision::S6, None) => format!("#!/bin/sh\nexec s6-log -b {}\n", dir),
+        })
+    }
+
+    // Output goes through the logger subservice to `/var/log/<name>/current` unless the spec
+    // names another directory
+    fn log_files(spec: &ServiceSpec) -> LogFiles {
+        match &spec.log_files {
+            Some(log_files) => log_files.clone(),
+            None => LogFiles::new(&format!("{}/{}/{}", LOG_DIR, spec.name, CURRENT)),
+        }
+    }
+
+    fn parse_runit_status(stdout: &str) -> ServiceStatus {
+        // run: /etc/sv/name: (pid 123) 45s; run: log: (pid 124) 45s
+        let service = stdout.split(';').next().unwrap_or("");
+        let mut status = ServiceStatus::new(match service.split(':').next().unwrap_or("") {
+            "run" | "finish" => ServiceState::Running,
+            "fail" => ServiceState::Failed,
+            _ => ServiceState::Stopped,
+        });
+        if status.is_running() {
+            status.main_pid = service.split("(pid ").nth(1)
+                .and_then(|rest| rest.split(')').next())
+                .and_then(|pid| pid.parse().ok());
+            status.uptime = service.split(") ").nth(1)
+                .and_then(|rest| rest.split([',', ' ']).next())
+                .and_then(|rest| rest.trim().strip_suffix('s'))
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs);
+        }
+        status
+    }
+
+    fn parse_s6_status(stdout: &str) -> ServiceStatus {
+        // up (pid 123 pgid 123) 45 seconds, or down (exitcode 1) 3 seconds, normally up
+        let field = |name: &str| stdout.split(&format!("({} ", name)).nth(1)
+            .and_then(|rest| rest.split([')', ' ']).next())
+            .map(str::to_string);
+        let exit_code = field("exitcode").and_then(|code| code.parse().ok());
+        let mut status = ServiceStatus::new(match stdout.split_whitespace().next() {
+            Some("up") => ServiceState::Running,
+            _ if exit_code.unwrap_or(0) != 0 => ServiceState::Failed,
+            _ => ServiceState::Stopped,
+        });
+        status.last_exit_code = exit_code;
+        if status.is_running() {
+            status.main_pid = field("pid").and_then(|pid| pid.parse().ok());
+            status.uptime = stdout.split(") ").nth(1)
+                .and_then(|rest| rest.split_whitespace().next())
+                .and_then(|secs| secs.parse().ok())
+                .map(Duration::from_secs);
+        }
+        status
+    }
+
+    fn service_dir(&self) -> PathBuf {
+        self.sv_dir.join(&self.spec.name)
+    }
+
+    fn link_path(&self) -> PathBuf {
+        self.scan_dir.join(&self.spec.name)
+    }
+
+    fn control(&self, args: &[&str]) -> crate::Result<tool::Output> {
+        let dir = self.service_dir();
+        let dir = dir.to_str()
+            .ok_or_else(|| crate::Error::new(crate::ErrorKind::Io,
+                                             "Cannot decode path".to_string())
+                .content(dir.display().to_string()))?;
+        let (program, mut args) = match self.supervision {
+            Supervision::Runit => ("sv", args.to_vec()),
+            Supervision::S6 => (args[0], args[1..].to_vec()),
+        };
+        args.push(dir);
+        tool::run(Path::new(program), program, &args)
+    }
+
+    fn up(&self) -> crate::Result<()> {
+        match self.supervision {
+            Supervision::Runit => self.control(&["up"])?.check().map(|_| ()),
+            Supervision::S6 => self.control(&["s6-svc", "-u"])?.check().map(|_| ()),
+        }
+    }
+
+    fn down(&self) -> crate::Result<()> {
+        let timeout = STOP_TIMEOUT.as_secs().to_string();
+        match self.supervision {
+            // force-stop kills the process when it outlives the timeout
+            Supervision::Runit => self.control(&["-w", &timeout, "force-stop"])?.check()
+                .map(|_| ()),
+            Supervision::S6 => {
+                let timeout = STOP_TIMEOUT.as_millis().to_string();
+                if self.control(&["s6-svc", "-d", "-wd", "-T", &timeout])?.check().is_err() {
+                    self.control(&["s6-svc", "-k"])?.check()?;
+                }
+                Ok(())
+            },
+        }
+    }
+
+    // Makes the supervisor pick up or drop the directories of the scan directory
+    fn rescan(&self) -> crate::Result<()> {
+        match self.supervision {
+            // runsvdir looks at its directory every five seconds
+            Supervision::Runit => Ok(()),
+            Supervision::S6 => {
+                let scan_dir = self.scan_dir.display().to_string();
+                tool::run(Path::new("s6-svscanctl"), "s6-svscanctl", &["-an", &scan_dir])?
+                    .check()
+                    .map(|_| ())
+            },
+        }
+    }
+
+    // Waits until runsv or s6-supervise controls the service
+    fn wait_supervised(&self) -> crate::Result<()> {
+        let control = self.service_dir().join("supervise").join(match self.supervision {
+            Supervision::Runit => "ok",
+            Supervision::S6 => "control",
+        });
+        let deadline = Instant::now() + SCAN_TIMEOUT;
+        while !control.exists() {
+            if Instant::now() > deadline {
+                return Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                             format!("Service {} is not supervised, is {} \
+                                                      scanning {}?", self.spec.name,
+                                                     self.supervision.name(),
+                                                     self.scan_dir.display()))
+                    .content(control.display().to_string()));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    // Spec the service was created from, as recorded in its run script
+    fn stored_spec(&self) -> crate::Result<Option<ServiceSpec>> {
+        let script = std::fs::read_to_string(self.service_dir().join("run"))?;
+        Ok(marker::parse_comment(&self.spec.name, &script).map(|marker| marker.spec))
+    }
+
+    // Loggers append to current and rotate it themselves, returns the sizes before start
+    fn prepare_logs(log_files: &LogFiles) -> crate::Result<Vec<u64>> {
+        if log_files.mode == FileMode::Truncate {
+            match std::fs::OpenOptions::new().write(true).open(&log_files.stdout) {
+                Ok(file) => file.set_len(0)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => return Err(crate::Error::from(e)
+                    .content(log_files.stdout.display().to_string())),
+            }
+        }
+        Ok(log_files.sizes())
+    }
+
+    // Starts the service, then waits for the readiness probe reading the output files
+    fn launch(&self, readiness: Option<&Readiness>, log_files: &LogFiles) -> crate::Result<()> {
+        let sizes = SombraServiceDir::prepare_logs(log_files)?;
+        self.up()?;
+        match readiness {
+            Some(readiness) => readiness.wait(&self.spec.name, || log_files.output_since(&sizes)),
+            None => Ok(()),
+        }
+    }
+
+    fn write_script(path: &Path, content: &str) -> crate::Result<()> {
+        std::fs::write(path, content)?;
+        Ok(std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?)
+    }
+
+    // Lays out `<sv_dir>/<name>` with its run, finish and log/run scripts
+    fn write_service_dir(&self) -> crate::Result<()> {
+        let dir = self.service_dir();
+        std::fs::create_dir_all(dir.join("log"))?;
+        let run = self.run_content(&Marker::new(&self.spec))?;
+        SombraServiceDir::write_script(&dir.join("run"), &run)?;
+        if let Some(finish) = self.finish_content() {
+            SombraServiceDir::write_script(&dir.join("finish"), &finish)?;
+        }
+        SombraServiceDir::write_script(&dir.join("log").join("run"), &self.log_run_content()?)?;
+        if self.spec.startup != StartupMode::Boot {
+            std::fs::write(dir.join("down"), "")?;
+        }
+
+        let log_files = SombraServiceDir::log_files(&self.spec);
+        let log_dir = log_dir(&log_files);
+        std::fs::create_dir_all(log_dir)?;
+        if let (Supervision::Runit, Some(max_size)) = (self.supervision, log_files.max_size) {
+            std::fs::write(log_dir.join("config"),
+                           format!("s{}\nn{}\n", max_size, log_files.retain))?;
+        }
+        // s6-supervise wants a timeout longer than the restart delay
+        if let (Supervision::S6, Some(delay)) = (self.supervision, self.spec.restart.delay) {
+            std::fs::write(dir.join("timeout-finish"),
+                           format!("{}\n", (seconds(delay) + 5) * 1000))?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, force: bool) -> crate::Result<()> {
+        self.check_privileges("Deleting")?;
+        if !force && self.stored_spec()?.is_none() {
+            return Err(crate::Error::new(crate::ErrorKind::NotManaged,
+                                         format!("Service {} was not created by sombra",
+                                                 self.spec.name)));
+        }
+        let _ = std::fs::remove_file(self.link_path());
+        let _ = self.down();
+        // Without its link the supervisor of the service exits, after the logger drains
+        let _ = match self.supervision {
+            Supervision::Runit => self.control(&["exit"]).map(|_| ()),
+            Supervision::S6 => self.rescan(),
+        };
+        Ok(std::fs::remove_dir_all(self.service_dir())?)
+    }
+
+    // Writes the service directory and links it into the scan directory, returns the sizes
+    // of the output files from before the service could start
+    fn install(&self, log_files: &LogFiles) -> crate::Result<Vec<u64>> {
+        self.write_service_dir()?;
+        // A boot service starts as soon as it is supervised, take the sizes before
+        let sizes = SombraServiceDir::prepare_logs(log_files)?;
+        std::fs::create_dir_all(&self.scan_dir)?;
+        std::os::unix::fs::symlink(self.service_dir(), self.link_path())?;
+
+        if self.spec.startup == StartupMode::Disabled {
+            // Supervised from the next scan on, down until enabled
+            let _ = self.rescan();
+        } else {
+            self.rescan()?;
+            self.wait_supervised()?;
+        }
+        Ok(sizes)
+    }
+
+    // Undoes a failed install, so creating the service again does not find it half there
+    fn uninstall(&self) {
+        // The link may belong to another service directory if that is what failed
+        if std::fs::read_link(self.link_path()).ok() == Some(self.service_dir()) {
+            let _ = std::fs::remove_file(self.link_path());
+            let _ = self.rescan();
+        }
+        let _ = std::fs::remove_dir_all(self.service_dir());
+    }
+
+    fn check_exists(&self) -> crate::Result<()> {
+        if self.service_dir().join("run").exists() {
+            Ok(())
+        } else {
+            Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} does not exist",
+                                                                self.spec.name)))
+        }
+    }
+
+    fn check_privileges(&self, operation: &str) -> crate::Result<()> {
+        if account::is_privileged(&self.scan_dir) {
+            return Ok(());
+        }
+        Err(crate::Error::new(crate::ErrorKind::PermissionDenied,
+                              format!("{} service {} needs root privileges",
+                                      operation, self.spec.name))
+            .content(self.scan_dir.display().to_string()))
+    }
+}
+
+fn log_dir(log_files: &LogFiles) -> &Path {
+    log_files.stdout.parent().unwrap_or_else(|| Path::new(LOG_DIR))
+}
+
+fn quote_path(path: &Path) -> crate::Result<String> {
+    match path.to_str() {
+        Some(path) => Ok(shell::quote(path)),
+        None => Err(crate::Error::new(crate::ErrorKind::Io, "Cannot decode path".to_string())
+            .content(path.display().to_string())),
+    }
+}
+
+// Rounding up keeps short delays from vanishing, not every sleep takes fractions
+fn seconds(duration: Duration) -> u64 {
+    duration.as_millis().div_ceil(1000) as u64
+}
+
+fn unsupported(supervision: Supervision, what: &str) -> crate::Error {
+    crate::Error::new(crate::ErrorKind::Unsupported,
+                      format!("{} not supported by {}", what, supervision.name()))
+}
+
+impl Sombra for SombraServiceDir {
+    fn from_spec(spec: ServiceSpec) -> crate::Result<Self> {
+        match Backend::detect()? {
+            Backend::S6 => SombraServiceDir::with_supervision(spec, Supervision::S6),
+            _ => SombraServiceDir::with_supervision(spec, Supervision::Runit),
+        }
+    }
+
+    fn list_in(scope: Scope) -> crate::Result<Vec<Self>> {
+        match Backend::detect()? {
+            Backend::S6 => SombraServiceDir::list_supervised(scope, Supervision::S6),
+            _ => SombraServiceDir::list_supervised(scope, Supervision::Runit),
+        }
+    }
+
+    fn spec(&self) -> &ServiceSpec {
+        &self.spec
+    }
+
+    fn create(&self) -> crate::Result<()> {
+        self.check_privileges("Creating")?;
+
+        if self.service_dir().exists() {
+            return Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} already exist",
+                                         self.spec.name)));
+        }
+        let log_files = SombraServiceDir::log_files(&self.spec);
+        let sizes = match self.install(&log_files) {
+            Ok(sizes) => sizes,
+            Err(e) => {
+                self.uninstall();
+                return Err(e);
+            },
+        };
+        if self.spec.startup == StartupMode::Disabled {
+            return Ok(());
+        }
+        self.up()?;
+        if let Some(readiness) = &self.spec.readiness {
+            return readiness.wait(&self.spec.name, || log_files.output_since(&sizes));
+        }
+
+        // Without a probe, give the process a moment to fail
+        std::thread::sleep(Duration::from_millis(100));
+        let status = self.status()?;
+        if !status.is_running() {
+            return Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                         format!("Service {} is {} after start, expected running",
+                                                 self.spec.name, status.state))
+                .content(self.service_dir().display().to_string()));
+        }
+        Ok(())
+    }
+
+    fn delete(&self) -> crate::Result<()> {
+        self.remove(false)
+    }
+
+    fn force_delete(&self) -> crate::Result<()> {
+        self.remove(true)
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        self.check_privileges("Starting")?;
+        self.check_exists()?;
+        let stored = self.stored_spec()?.unwrap_or_else(|| self.spec.clone());
+        let readiness = match &self.spec.readiness {
+            Some(readiness) => Some(readiness.clone()),
+            None => stored.readiness.clone(),
+        };
+        self.launch(readiness.as_ref(), &SombraServiceDir::log_files(&stored))
+    }
+
+    fn stop(&self) -> crate::Result<()> {
+        self.check_privileges("Stopping")?;
+        self.check_exists()?;
+        self.down()
+    }
+
+    // The down file keeps the supervisor from starting the service when it starts itself
+    fn enable(&self) -> crate::Result<()> {
+        self.check_privileges("Enabling")?;
+        self.check_exists()?;
+        match std::fs::remove_file(self.service_dir().join("down")) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn disable(&self) -> crate::Result<()> {
+        self.check_privileges("Disabling")?;
+        self.check_exists()?;
+        Ok(std::fs::write(self.service_dir().join("down"), "")?)
+    }
+
+    fn status(&self) -> crate::Result<ServiceStatus> {
+        if !self.service_dir().join("run").exists() {
+            return Ok(ServiceStatus::new(ServiceState::NotInstalled));
+        }
+        // Both tools fail when nothing supervises the directory, which leaves it stopped
+        let mut status = match self.supervision {
+            Supervision::Runit => SombraServiceDir::parse_runit_status(
+                &self.control(&["status"])?.stdout),
+            Supervision::S6 => SombraServiceDir::parse_s6_status(
+                &self.control(&["s6-svstat"])?.stdout),
+        };
+        if status.is_running() {
+            status.health = crate::health::load(&crate::health::health_dir(self.spec.scope),
+                                                &self.spec.name);
+        }
+        Ok(status)
+    }
+
+    fn logs(&self, query: &LogQuery) -> crate::Result<Logs> {
+        let stored = match &self.spec.log_files {
+            Some(_) => None,
+            None => self.stored_spec().ok().flatten(),
+        };
+        SombraServiceDir::log_files(stored.as_ref().unwrap_or(&self.spec)).logs(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RestartPolicy;
+
+    fn service(spec: ServiceSpec, supervision: Supervision, dir: &Path) -> SombraServiceDir {
+        SombraServiceDir {
+            spec: spec.canonicalize().unwrap(),
+            supervision,
+            sv_dir: dir.join("sv"),
+            scan_dir: dir.join("service"),
+        }
+    }
+
+    #[test]
+    fn run_script_drops_privileges() {
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .args(vec!["-p".to_string(), "30 222".to_string()])
+            .user("root")
+            .env("GREETING", "it's $HOME");
+        let service = service(spec, Supervision::Runit, Path::new("/tmp"));
+        let run = service.run_content(&Marker::new(&service.spec)).unwrap();
+        let lines: Vec<_> = run.lines().collect();
+        assert_eq!(lines[0], "#!/bin/sh");
+        assert_eq!(lines[1], "exec 2>&1");
+        assert!(lines.contains(&"export GREETING='it'\\''s $HOME'"));
+        assert!(lines.iter().any(|line| line.starts_with("exec chpst -u 'root' '/")
+            && line.ends_with("/executables/tcp_echo' '-p' '30 222'")));
+        let marker = marker::parse_comment("tcp_echo", &run).unwrap();
+        assert_eq!(marker.spec.args, vec!["-p", "30 222"]);
+
+        let service = SombraServiceDir { supervision: Supervision::S6, ..service };
+        let run = service.run_content(&Marker::new(&service.spec)).unwrap();
+        assert!(run.contains("\nexec s6-setuidgid 'root' '/"));
+        assert_eq!(service.log_run_content().unwrap(),
+                   "#!/bin/sh\nexec s6-log -b '/var/log/tcp_echo'\n");
+    }
+
+    #[test]
+    fn finish_script_follows_restart_policy() {
+        let finish = |restart: RestartPolicy| {
+            let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo").restart(restart);
+            service(spec, Supervision::Runit, Path::new("/tmp")).finish_content()
+        };
+        assert_eq!(finish(RestartPolicy::new(RestartMode::Always)), None);
+        assert_eq!(finish(RestartPolicy::new(RestartMode::Never)).unwrap(),
+                   "#!/bin/sh\ncase \"$1\" in\n\t*) exec sv down . ;;\nesac\n");
+        assert_eq!(finish(RestartPolicy::new(RestartMode::OnFailure)
+                       .delay(Duration::from_millis(1500))).unwrap(),
+                   "#!/bin/sh\ncase \"$1\" in\n\t0) exec sv down . ;;\nesac\nexec sleep 2\n");
+
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .restart(RestartPolicy::new(RestartMode::OnAbnormal).prevent_exit_code(4));
+        let error = SombraServiceDir::with_supervision(spec, Supervision::Runit).err().unwrap();
+        assert_eq!(error.kind(), &crate::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn marker_keeps_line_breaks() {
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .description("Echo\nserver")
+            .arg("x\nLogStdout=/etc/current");
+        let service = service(spec, Supervision::Runit, Path::new("/tmp"));
+        let run = service.run_content(&Marker::new(&service.spec)).unwrap();
+        let marker = marker::parse_comment("tcp_echo", &run).unwrap();
+        assert_eq!(marker.spec.description, Some("Echo\nserver".to_string()));
+        assert_eq!(marker.spec.args, vec!["x\nLogStdout=/etc/current"]);
+        assert_eq!(marker.spec.log_files, None);
+    }
+
+    #[test]
+    fn parse_status_output() {
+        let status = SombraServiceDir::parse_runit_status(
+            "run: /etc/sv/tcp_echo: (pid 123) 45s; run: log: (pid 124) 45s\n");
+        assert_eq!(status.state, ServiceState::Running);
+        assert_eq!(status.main_pid, Some(123));
+        assert_eq!(status.uptime, Some(Duration::from_secs(45)));
+        let status = SombraServiceDir::parse_runit_status(
+            "down: /etc/sv/tcp_echo: 3s, normally up; run: log: (pid 124) 45s\n");
+        assert_eq!(status.state, ServiceState::Stopped);
+        let status = SombraServiceDir::parse_runit_status(
+            "run: /etc/sv/tcp_echo: (pid 123) 7s, normally down; run: log: (pid 124) 45s\n");
+        assert_eq!(status.uptime, Some(Duration::from_secs(7)));
+        assert_eq!(SombraServiceDir::parse_runit_status("").state, ServiceState::Stopped);
+
+        let status = SombraServiceDir::parse_s6_status("up (pid 123 pgid 123) 45 seconds\n");
+        assert_eq!(status.state, ServiceState::Running);
+        assert_eq!(status.main_pid, Some(123));
+        assert_eq!(status.uptime, Some(Duration::from_secs(45)));
+        let status = SombraServiceDir::parse_s6_status(
+            "down (exitcode 2) 3 seconds, normally up, want up\n");
+        assert_eq!(status.state, ServiceState::Failed);
+        assert_eq!(status.last_exit_code, Some(2));
+        let status = SombraServiceDir::parse_s6_status("down (signal SIGTERM) 5 seconds\n");
+        assert_eq!(status.state, ServiceState::Stopped);
+    }
+
+    struct TempDir {
+        path: PathBuf,
+    }
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("sombra-svdir-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(path.join("log")).unwrap();
+            TempDir { path }
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn disabled_service_is_linked_down() {
+        let temp = TempDir::new("down");
+        let dir = &temp.path;
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .startup(StartupMode::Disabled)
+            .log_files(LogFiles::new(dir.join("log").join(CURRENT).to_str().unwrap())
+                .rotate(4096, 3));
+        let service = service(spec, Supervision::Runit, dir);
+        service.create().unwrap();
+        let service_dir = dir.join("sv").join("tcp_echo");
+        assert_eq!(std::fs::read_link(dir.join("service").join("tcp_echo")).unwrap(),
+                   service_dir);
+        assert!(service_dir.join("down").exists());
+        assert!(service_dir.join("finish").exists());
+        assert_eq!(std::fs::read_to_string(dir.join("log").join("config")).unwrap(), "s4096\nn3\n");
+        assert_eq!(service.stored_spec().unwrap().unwrap().name, "tcp_echo");
+
+        service.enable().unwrap();
+        assert!(!service_dir.join("down").exists());
+        service.delete().unwrap();
+        assert!(!service_dir.exists());
+        assert!(!dir.join("service").join("tcp_echo").exists());
+    }
+
+    #[test]
+    fn failed_create_leaves_nothing() {
+        let temp = TempDir::new("failed");
+        let dir = &temp.path;
+        // The scan directory cannot be created over a file
+        std::fs::write(dir.join("service"), "").unwrap();
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .startup(StartupMode::Disabled)
+            .log_files(LogFiles::new(dir.join("log").join(CURRENT).to_str().unwrap()));
+        let service = service(spec, Supervision::Runit, dir);
+        assert!(service.create().is_err());
+        assert!(!dir.join("sv").join("tcp_echo").exists());
+    }
+}
@@ -0,0 +1,65 @@
+// Quoting of values written to the shell scripts of OpenRC, SysV init and runit
+
+/// Wraps a word in single quotes, the only character they cannot hold is the quote itself
+pub fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+/// Wraps a value in double quotes, escaping what the shell still expands inside them
+pub fn double_quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        if matches!(c, '\\' | '$' | '`' | '"') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Renders a value the init script later passes through `eval`, each word quoted again
+pub fn eval_words<S: AsRef<str>>(words: &[S]) -> String {
+    let words: Vec<_> = words.iter().map(|word| quote(word.as_ref())).collect();
+    double_quote(&words.join(" "))
+}
+
+/// Words needing no quoting at all, which is what some init scripts require of paths
+pub fn is_plain(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric() || "/._-+=:,@%".contains(c))
+}
+
+/// Renders `export KEY='value'`
+pub fn export(key: &str, value: &str) -> crate::Result<String> {
+    crate::env::check_name(key)?;
+    Ok(format!("export {}={}", key, quote(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn eval_words_survive_two_expansions() {
+        let words = ["it's", "$HOME `id`", "\"quoted\""];
+        let output = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(format!("args={}; eval printf \"'%s|'\" $args", eval_words(&words)))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "it's|$HOME `id`|\"quoted\"|");
+    }
+
+    #[test]
+    fn quoted_words_survive_the_shell() {
+        let words = ["it's", "$HOME `id`", "a\nb", "\\"];
+        let quoted: Vec<_> = words.iter().map(|word| quote(word)).collect();
+        let output = Command::new("/bin/sh")
+            .arg("-c")
+            .arg(format!("printf '%s|' {}", quoted.join(" ")))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "it's|$HOME `id`|a\nb|\\|");
+    }
+}
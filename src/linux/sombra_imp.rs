@@ -1,120 +1,598 @@
-use crate::Sombra;
-use std::path::PathBuf;
+use crate::{Sombra, FileMode, LogFiles, LogQuery, Logs, Readiness, RestartMode, RestartPolicy, Scope, ServiceSpec, ServiceState, ServiceStatus,
+            StartupMode};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::io::Write;
+use std::time::{Duration, SystemTime};
 use crate::linux::systemctl::Systemctl;
+use crate::linux::marker;
+use crate::linux::escape;
+use crate::linux::account;
+use crate::linux::journalctl;
+use crate::linux::loginctl;
+use crate::marker::Marker;
+use crate::spec::WorkingDirectory;
 use crate::error::ErrorKind::Other;
 
+const UNIT_DIR: &str = "/etc/systemd/system";
+const JOURNALCTL: &str = "journalctl";
+
 pub struct SombraLinux {
-    process_path: PathBuf,
-    process_name: String,
-    process_args: Vec<String>,
+    spec: ServiceSpec,
     sysctl: Systemctl,
+    journalctl: PathBuf,
+    unit_dir: PathBuf,
 }
 
 impl SombraLinux {
-    fn service_file_content(name: &str, path: &PathBuf, args: &Vec<String>) -> crate::Result<String> {
-        let path_str = match path.to_str() {
-            Some(path_str) => path_str.to_string(),
-            None => return Err(crate::Error::new(crate::ErrorKind::Io,
-                                                 "Cannot decode path".to_string()))
+    fn service_file_content(marker: &Marker) -> crate::Result<String> {
+        let spec = &marker.spec;
+        let exec_start = escape::command(&spec.executable, &spec.args)?;
+        let description = match &spec.description {
+            Some(description) => description.clone(),
+            None => format!("{} service", spec.name),
         };
 
-        let exec_start = if args.is_empty() {
-            path_str
-        } else {
-            format!("{} {}", path_str, args.join(" "))
+        let description = escape::text("a description", &description)?;
+        let mut unit = vec![format!("Description={}", description)];
+        // The user manager has no network.target, nor accounts to switch to
+        if spec.scope == Scope::System {
+            unit.push("After=network.target".to_string());
+        }
+        match spec.restart.burst {
+            Some((count, interval)) => {
+                unit.push(format!("StartLimitIntervalSec={}", timespan(interval)));
+                unit.push(format!("StartLimitBurst={}", count));
+            },
+            None => unit.push("StartLimitIntervalSec=0".to_string()),
+        }
+
+        let mut service = vec!["Type=simple".to_string()];
+        // The account running sombra does not exist in another root, services there run as root
+        let user = match (&spec.user, &spec.root) {
+            (Some(user), _) => Some(user.clone()),
+            (None, None) => Some(whoami::username()),
+            (None, Some(_)) => None,
         };
+        if let (Scope::System, Some(user)) = (spec.scope, user) {
+            service.push(format!("User={}", escape::text("a user name", &user)?));
+        }
+        if let Some(group) = &spec.group {
+            service.push(format!("Group={}", escape::text("a group name", group)?));
+        }
+        if !spec.supplementary_groups.is_empty() {
+            let groups = spec.supplementary_groups.iter()
+                .map(|group| escape::text("a group name", group))
+                .collect::<crate::Result<Vec<_>>>()?;
+            service.push(format!("SupplementaryGroups={}", groups.join(" ")));
+        }
+        // Only the marker records it, a line break would end the entry
+        if let Some(env_file) = &spec.env_file {
+            escape::path(env_file)?;
+        }
+        if !spec.ambient_capabilities.is_empty() {
+            service.push(format!("AmbientCapabilities={}", spec.ambient_capabilities.join(" ")));
+        }
+        for (key, value) in spec.environment()? {
+            service.push(format!("Environment={}", escape::env_assignment(&key, &value)?));
+        }
+        if let Some(WorkingDirectory::Path(path)) = &spec.working_directory {
+            service.push(format!("WorkingDirectory={}", escape::path(path)?));
+        }
+        if let Some(log_files) = &spec.log_files {
+            let mode = match log_files.mode {
+                FileMode::Append => "append",
+                FileMode::Truncate => "truncate",
+            };
+            service.push(format!("StandardOutput={}:{}", mode,
+                                 escape::path(&log_files.stdout)?));
+            match &log_files.stderr {
+                Some(stderr) => service.push(format!("StandardError={}:{}", mode,
+                                                     escape::path(stderr)?)),
+                None => service.push("StandardError=inherit".to_string()),
+            }
+        }
+        service.push(format!("ExecStart={}", exec_start));
+        service.extend(SombraLinux::restart_directives(&spec.restart));
+
         Ok(format!("[Unit]\n\
-                Description={} service\n\
-                After=network.target\n\
-                StartLimitIntervalSec=0\n\
+                {}\n\
                 \n\
                 [Service]\n\
-                Type=simple\n\
-                User={}\n\
-                ExecStart={}\n\
+                {}\n\
                 \n\
                 [Install]\n\
-                WantedBy=multi-user.target",
-                name,
-                whoami::username(),
-                exec_start))
-    }
-
-    fn is_root() -> crate::Result<()> {
-        match std::env::var("USER") {
-            Err(e) => Err(crate::Error::new(Other, e.to_string())),
-            Ok(name) => {
-                if name != "root" {
-                    Err(crate::Error::new(Other,
-                                          "Without root privileges.".to_string()))
-                } else {
-                    Ok(())
-                }
+                WantedBy={}\n\
+                \n\
+                {}",
+                unit.join("\n"),
+                service.join("\n"),
+                wanted_by(spec.scope),
+                marker::render(marker)))
+    }
+
+    fn restart_directives(policy: &RestartPolicy) -> Vec<String> {
+        if *policy == RestartPolicy::default() {
+            return vec![];
+        }
+
+        let mode = match policy.mode {
+            RestartMode::Never => "no",
+            RestartMode::OnFailure => "on-failure",
+            RestartMode::Always => "always",
+            RestartMode::OnAbnormal => "on-abnormal",
+        };
+        let mut directives = vec![format!("Restart={}", mode)];
+        if let Some(delay) = policy.delay {
+            directives.push(format!("RestartSec={}", timespan(delay)));
+        }
+        // Needs systemd 254, older versions ignore both and keep the fixed delay
+        if let Some((max_delay, steps)) = policy.backoff {
+            directives.push(format!("RestartSteps={}", steps));
+            directives.push(format!("RestartMaxDelaySec={}", timespan(max_delay)));
+        }
+        let codes = |codes: &[i32]| codes.iter()
+            .map(|code| code.to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !policy.success_exit_codes.is_empty() {
+            directives.push(format!("SuccessExitStatus={}", codes(&policy.success_exit_codes)));
+        }
+        if !policy.prevent_exit_codes.is_empty() {
+            directives.push(format!("RestartPreventExitStatus={}",
+                                    codes(&policy.prevent_exit_codes)));
+        }
+        directives
+    }
+
+    fn parse_status(props: &HashMap<String, String>, now: Duration) -> ServiceStatus {
+        let prop = |key: &str| props.get(key).map(String::as_str).unwrap_or("");
+
+        let state = if prop("LoadState") == "not-found" {
+            ServiceState::NotInstalled
+        } else {
+            match (prop("ActiveState"), prop("SubState")) {
+                ("failed", _) | ("activating", "auto-restart") => ServiceState::Failed,
+                ("active", _) | ("reloading", _) | ("activating", _) => ServiceState::Running,
+                _ => ServiceState::Stopped,
+            }
+        };
+
+        let mut status = ServiceStatus::new(state);
+        // MainPID and the activation timestamp only describe a running unit
+        if state == ServiceState::Running {
+            status.main_pid = prop("MainPID").parse().ok().filter(|&pid| pid != 0);
+            status.uptime = prop("ActiveEnterTimestampMonotonic").parse().ok()
+                .filter(|&usec| usec != 0)
+                .map(Duration::from_micros)
+                .and_then(|since| now.checked_sub(since));
+        }
+
+        // ExecMainCode follows the waitid() si_code convention: 1 exited, 2 killed, 3 dumped
+        let main_status = prop("ExecMainStatus").parse::<i32>().ok();
+        status.last_exit_code = match prop("ExecMainCode") {
+            "1" => main_status,
+            "2" | "3" => main_status.map(|signal| 128 + signal),
+            _ => None,
+        };
+
+        status
+    }
+
+    fn monotonic_now() -> Duration {
+        let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+        unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+        Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+    }
+
+    // Where units of the scope are installed, `~/.config/systemd/user` for user services
+    fn unit_dir(scope: Scope, root: Option<&Path>) -> crate::Result<PathBuf> {
+        match (scope, root) {
+            (Scope::System, None) => return Ok(crate::linux::test_hook("SOMBRA_UNIT_DIR")
+                .unwrap_or_else(|| PathBuf::from(UNIT_DIR))),
+            (Scope::System, Some(root)) => return Ok(root.join(&UNIT_DIR[1..])),
+            (Scope::User, Some(_)) => return Err(crate::Error::new(
+                crate::ErrorKind::Unsupported,
+                "User services cannot be installed into a root directory".to_string())),
+            (Scope::User, None) => (),
+        }
+        match (std::env::var_os("XDG_CONFIG_HOME"), std::env::var_os("HOME")) {
+            (Some(config), _) if !config.is_empty() => Ok(Path::new(&config).join("systemd/user")),
+            (_, Some(home)) => Ok(Path::new(&home).join(".config/systemd/user")),
+            _ => Err(crate::Error::new(Other, "HOME is not set, cannot find the user unit \
+                                              directory".to_string())),
+        }
+    }
+
+    /// Drives the service through other systemctl and journalctl programs, with its unit
+    /// in `unit_dir`
+    #[cfg(test)]
+    pub(crate) fn with_manager(self, systemctl: &Path, journalctl: &Path, unit_dir: &Path)
+                               -> Self {
+        SombraLinux {
+            sysctl: Systemctl::with_program(&self.spec.name, self.spec.scope, systemctl),
+            journalctl: journalctl.to_path_buf(),
+            unit_dir: unit_dir.to_path_buf(),
+            spec: self.spec,
+        }
+    }
+
+    // Services created by sombra among the units of the directory
+    fn list_units(scope: Scope, unit_dir: &Path) -> crate::Result<Vec<Self>> {
+        let mut services = vec![];
+        for entry in std::fs::read_dir(unit_dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "service") {
+                continue;
             }
+            let name = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let marker = match std::fs::read_to_string(&path).ok()
+                .and_then(|unit| marker::parse(&name, &unit)) {
+                Some(marker) => marker,
+                None => continue,
+            };
+
+            services.push(SombraLinux {
+                sysctl: Systemctl::new(&name, scope),
+                journalctl: PathBuf::from(JOURNALCTL),
+                unit_dir: unit_dir.to_path_buf(),
+                spec: ServiceSpec { scope, ..marker.spec },
+            });
+        }
+        services.sort_by(|a, b| a.spec.name.cmp(&b.spec.name));
+
+        Ok(services)
+    }
+
+    fn unit_path(&self) -> PathBuf {
+        self.unit_dir.join(format!("{}.service", self.spec.name))
+    }
+
+    // Symlink `systemctl enable` would create in the wants directory of the install target
+    fn wants_path(&self) -> PathBuf {
+        self.unit_dir.join(format!("{}.wants", wanted_by(self.spec.scope)))
+            .join(format!("{}.service", self.spec.name))
+    }
+
+    // Enables a service installed into a root directory, where no systemd is running
+    fn link(&self) -> crate::Result<()> {
+        let wants = self.wants_path();
+        if let Some(dir) = wants.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        if std::fs::symlink_metadata(&wants).is_ok() {
+            std::fs::remove_file(&wants)?;
+        }
+        // Absolute like the links of systemctl, so it resolves once the root is mounted at /
+        let target = Path::new(UNIT_DIR).join(format!("{}.service", self.spec.name));
+        std::os::unix::fs::symlink(target, &wants)
+            .map_err(|e| crate::Error::from(e).content(wants.display().to_string()))
+    }
+
+    fn unlink(&self) -> crate::Result<()> {
+        match std::fs::remove_file(self.wants_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    // Services installed into a root directory can be enabled, but not run from here
+    fn check_online(&self) -> crate::Result<()> {
+        match &self.spec.root {
+            Some(root) => Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                                format!("Service {} is installed into another \
+                                                         root directory, it cannot run from here",
+                                                        self.spec.name))
+                .content(root.display().to_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn remove(&self, force: bool) -> crate::Result<()> {
+        self.check_privileges("Deleting")?;
+        let unit = std::fs::read_to_string(self.unit_path())?;
+        if !force && marker::parse(&self.spec.name, &unit).is_none() {
+            return Err(crate::Error::new(crate::ErrorKind::NotManaged,
+                                         format!("Service {} was not created by sombra",
+                                                 self.spec.name)));
+        }
+
+        if self.spec.root.is_some() {
+            self.unlink()?;
+            return Ok(std::fs::remove_file(self.unit_path())?);
+        }
+        let _ = self.sysctl.stop();
+        self.sysctl.disable()?;
+        std::fs::remove_file(self.unit_path())?;
+        self.sysctl.daemon_reload()?;
+        self.sysctl.reset_failed()
+    }
+
+    // Starts the unit, then waits for the readiness probe, reading the log from the
+    // output files when the service writes to files
+    fn launch(&self, readiness: Option<&Readiness>, log_files: Option<&LogFiles>)
+              -> crate::Result<()> {
+        let sizes = match log_files {
+            Some(log_files) => {
+                log_files.rotate_files()?;
+                log_files.sizes()
+            },
+            None => vec![],
+        };
+        let started = SystemTime::now();
+        self.sysctl.start()?;
+        match readiness {
+            Some(readiness) => readiness.wait(&self.spec.name, || match log_files {
+                Some(log_files) => log_files.output_since(&sizes),
+                None => journalctl::messages_since(&self.journalctl, self.spec.scope,
+                                                   &self.spec.name, started),
+            }),
+            None => Ok(()),
         }
     }
+
+    // Spec the service was created from, as recorded in its unit
+    fn stored_spec(&self) -> crate::Result<Option<ServiceSpec>> {
+        let unit = std::fs::read_to_string(self.unit_path())?;
+        Ok(marker::parse(&self.spec.name, &unit).map(|marker| marker.spec))
+    }
+
+    fn check_exists(&self) -> crate::Result<()> {
+        if self.unit_path().exists() {
+            Ok(())
+        } else {
+            Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} does not exist",
+                                                                self.spec.name)))
+        }
+    }
+
+    // User services are managed by the user manager, which needs no privileges
+    fn check_privileges(&self, operation: &str) -> crate::Result<()> {
+        if self.spec.scope == Scope::User || account::is_privileged(&self.unit_dir) {
+            return Ok(());
+        }
+        Err(crate::Error::new(crate::ErrorKind::PermissionDenied,
+                              format!("{} service {} needs root privileges",
+                                      operation, self.spec.name))
+            .content(self.unit_dir.display().to_string()))
+    }
 }
 
-macro_rules! sombra_error {
-    ($kind:ident, $content:expr) => {
-        |e| crate::Error::new(crate::ErrorKind::$kind, e.to_string()).content($content)
-    };
+fn wanted_by(scope: Scope) -> &'static str {
+    match scope {
+        Scope::System => "multi-user.target",
+        Scope::User => "default.target",
+    }
+}
+
+fn timespan(duration: Duration) -> String {
+    format!("{}ms", duration.as_millis())
 }
 
 impl Sombra for SombraLinux {
-    fn build(name: &str, path: &str, args: Vec<String>) -> crate::Result<Self> {
-        let path = dunce::canonicalize(path)
-            .map_err(sombra_error!(Io, path.to_string()))?;
+    fn from_spec(spec: ServiceSpec) -> crate::Result<Self> {
+        let spec = spec.canonicalize()?;
+        // Accounts of another root directory are not known here
+        if spec.root.is_none() {
+            if let Some(user) = &spec.user {
+                account::user(user)?;
+            }
+            for group in spec.group.iter().chain(&spec.supplementary_groups) {
+                account::group(group)?;
+            }
+        }
+        if spec.scope == Scope::User && (spec.user.is_some() || spec.group.is_some()
+            || !spec.supplementary_groups.is_empty() || !spec.ambient_capabilities.is_empty()) {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "User services run as the current user, without \
+                                          other accounts, groups or capabilities".to_string()));
+        }
+        if spec.linger && spec.scope != Scope::User {
+            return Err(crate::Error::new(Other, "Lingering only applies to user services"
+                .to_string()));
+        }
 
         Ok(SombraLinux {
-            process_path: path,
-            process_name: name.to_string(),
-            process_args: args,
-            sysctl: Systemctl::new(name)
+            sysctl: Systemctl::new(&spec.name, spec.scope),
+            journalctl: PathBuf::from(JOURNALCTL),
+            unit_dir: SombraLinux::unit_dir(spec.scope, spec.root.as_deref())?,
+            spec,
         })
     }
 
+    fn list_in(scope: Scope) -> crate::Result<Vec<Self>> {
+        let unit_dir = SombraLinux::unit_dir(scope, None)?;
+        // The user unit directory only exists once something was installed there
+        if scope == Scope::User && !unit_dir.exists() {
+            return Ok(vec![]);
+        }
+        SombraLinux::list_units(scope, &unit_dir)
+    }
+
+    fn spec(&self) -> &ServiceSpec {
+        &self.spec
+    }
+
     fn create(&self) -> crate::Result<()> {
-        SombraLinux::is_root()?;
+        self.check_privileges("Creating")?;
 
-        let path = std::path::PathBuf::from(
-            format!("/etc/systemd/system/{}.service", self.process_name));
+        let path = self.unit_path();
         if path.exists() {
             return Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} already exist",
-                                     self.process_name)));
+                                     self.spec.name)));
         } else {
+            std::fs::create_dir_all(&self.unit_dir)?;
+            let buffer = SombraLinux::service_file_content(&Marker::new(&self.spec))?;
             let mut file = std::fs::File::create(&path)?;
-            let buffer = SombraLinux::service_file_content(&self.process_name,
-                                                           &self.process_path,
-                                                           &self.process_args)?;
-            file.write_all(&mut buffer.as_bytes())?;
+            file.write_all(buffer.as_bytes())?;
         }
 
-        self.sysctl.start()?;
+        if self.spec.root.is_some() {
+            return match self.spec.startup {
+                StartupMode::Boot => self.link(),
+                StartupMode::Manual | StartupMode::Disabled => Ok(()),
+            };
+        }
+        if self.spec.linger {
+            loginctl::enable_linger()?;
+        }
+        match self.spec.startup {
+            StartupMode::Disabled => return Ok(()),
+            StartupMode::Boot => self.sysctl.enable()?,
+            StartupMode::Manual => (),
+        }
+        self.launch(self.spec.readiness.as_ref(), self.spec.log_files.as_ref())?;
+        if self.spec.readiness.is_some() {
+            return Ok(());
+        }
 
-        // Need a delay after creation on linux version
+        // Without a probe, give the process a moment to fail, since a simple
+        // service is reported started as soon as it is forked
         std::thread::sleep(std::time::Duration::from_millis(100));
-
+        let state = self.sysctl.active_state()?;
+        if state != "active" {
+            return Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                         format!("Service {} is {} after start, expected active",
+                                                 self.spec.name, state))
+                .content(format!("systemctl is-active {}", self.spec.name)));
+        }
         Ok(())
     }
 
     fn delete(&self) -> crate::Result<()> {
-        let _ = self.sysctl.stop();
-        self.sysctl.disable()?;
-        std::fs::remove_file(format!("/etc/systemd/system/{}.service", self.process_name))?;
-        Systemctl::daemon_reload()?;
-        Systemctl::reset_failed()
+        self.remove(false)
+    }
+
+    fn force_delete(&self) -> crate::Result<()> {
+        self.remove(true)
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        self.check_online()?;
+        self.check_privileges("Starting")?;
+        self.check_exists()?;
+        let stored = self.stored_spec()?;
+        let readiness = match &self.spec.readiness {
+            Some(readiness) => Some(readiness.clone()),
+            None => stored.as_ref().and_then(|spec| spec.readiness.clone()),
+        };
+        let log_files = stored.and_then(|spec| spec.log_files);
+        self.launch(readiness.as_ref(), log_files.as_ref())
+    }
+
+    fn stop(&self) -> crate::Result<()> {
+        self.check_online()?;
+        self.check_privileges("Stopping")?;
+        self.check_exists()?;
+        self.sysctl.stop()
+    }
+
+    fn restart(&self) -> crate::Result<()> {
+        self.check_online()?;
+        self.check_privileges("Restarting")?;
+        self.check_exists()?;
+        self.sysctl.restart()
+    }
+
+    fn enable(&self) -> crate::Result<()> {
+        self.check_privileges("Enabling")?;
+        self.check_exists()?;
+        if self.spec.root.is_some() {
+            return self.link();
+        }
+        self.sysctl.enable()
+    }
+
+    fn disable(&self) -> crate::Result<()> {
+        self.check_privileges("Disabling")?;
+        self.check_exists()?;
+        if self.spec.root.is_some() {
+            return self.unlink();
+        }
+        self.sysctl.disable()
+    }
+
+    fn status(&self) -> crate::Result<ServiceStatus> {
+        self.check_online()?;
+        let props = self.sysctl.show(&["LoadState", "ActiveState", "SubState", "MainPID",
+                                       "ActiveEnterTimestampMonotonic",
+                                       "ExecMainCode", "ExecMainStatus"])?;
+        let mut status = SombraLinux::parse_status(&props, SombraLinux::monotonic_now());
+        if status.is_running() {
+            status.health = crate::health::load(&crate::health::health_dir(self.spec.scope),
+                                                &self.spec.name);
+        }
+        Ok(status)
+    }
+
+    fn logs(&self, query: &LogQuery) -> crate::Result<Logs> {
+        self.check_online()?;
+        let log_files = match &self.spec.log_files {
+            Some(log_files) => Some(log_files.clone()),
+            None => self.stored_spec().ok().flatten().and_then(|spec| spec.log_files),
+        };
+        match log_files {
+            Some(log_files) => log_files.logs(query),
+            None => journalctl::logs(&self.journalctl, self.spec.scope, &self.spec.name, query),
+        }
     }
 }
 
 #[cfg(test)]
 #[cfg(target_os = "linux")]
+// The tests kept from the first release predate these lints
+#[allow(non_fmt_panics, clippy::unused_io_amount,
+        clippy::io_other_error)]
 mod tests {
     use super::*;
     use std::net::TcpStream;
     use std::io::Read;
     use std::time::Duration;
+    use proptest::prelude::*;
+    use crate::{HealthAction, Liveness, Monitor, Probe, Readiness};
+
+    // Unit directory of its own, with links to the scripted systemctl and journalctl, which
+    // run the services themselves. Services left running are killed even if the test panics.
+    struct FakeSystemd {
+        dir: PathBuf,
+    }
+
+    impl FakeSystemd {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("sombra-systemd-{}-{}", test, std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            for tool in &["systemctl", "journalctl"] {
+                let script = Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join(format!("tests/fake_{}.sh", tool));
+                std::os::unix::fs::symlink(script, dir.join(tool)).unwrap();
+            }
+            FakeSystemd { dir }
+        }
+
+        fn manage(&self, s: SombraLinux) -> SombraLinux {
+            s.with_manager(&self.dir.join("systemctl"), &self.dir.join("journalctl"), &self.dir)
+        }
+    }
+
+    impl Drop for FakeSystemd {
+        fn drop(&mut self) {
+            let pids = std::fs::read_dir(self.dir.join(".systemctl")).into_iter().flatten()
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "pid"))
+                .filter_map(|path| std::fs::read_to_string(path).ok()?.trim().parse::<i32>().ok());
+            for pid in pids {
+                // Each service runs in a session of its own, led by the pid
+                unsafe { libc::kill(-pid as libc::pid_t, libc::SIGKILL) };
+            }
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
 
     fn echo_check(ip_port: &str, msg: &[u8]) -> std::io::Result<()> {
         std::thread::sleep(Duration::from_millis(10)); // Need to allow tcp_echo open TCP connection
@@ -134,12 +612,14 @@ mod tests {
 
     #[test]
     fn spawn_simple() {
-        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo", vec![]) {
-            Ok(s) => s,
+        let systemd = FakeSystemd::new("spawn_simple");
+        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                     vec!["-p".to_string(), "30301".to_string()]) {
+            Ok(s) => systemd.manage(s),
             Err(e) => panic!(e.to_string()),
         };
         assert_eq!(s.create(), Ok(()));
-        let res = echo_check("127.0.0.1:30222", b"sombra30222");
+        let res = echo_check("127.0.0.1:30301", b"sombra30301");
         assert_eq!(s.delete(), Ok(()));
         if let Err(e) = res {
             panic!(format!("{:?}", e));
@@ -148,16 +628,19 @@ mod tests {
 
     #[test]
     fn spawn_twice_same_name() {
-        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo", vec![]) {
-            Ok(s) => s,
+        let systemd = FakeSystemd::new("spawn_twice_same_name");
+        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                     vec!["-p".to_string(), "30302".to_string()]) {
+            Ok(s) => systemd.manage(s),
             Err(e) => panic!(e.to_string()),
         };
         assert_eq!(s.create(), Ok(()));
 
-        match echo_check("127.0.0.1:30222", b"sombra30222") {
+        match echo_check("127.0.0.1:30302", b"sombra30302") {
             Ok(_) => {
-                let s2 = match SombraLinux::build("tcp_echo", "executables/tcp_echo", vec![]) {
-                    Ok(s2) => s2,
+                let s2 = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                              vec!["-p".to_string(), "30302".to_string()]) {
+                    Ok(s2) => systemd.manage(s2),
                     Err(e) => panic!(e.to_string()),
                 };
                 assert_ne!(s2.create(), Ok(()));
@@ -172,24 +655,25 @@ mod tests {
 
     #[test]
     fn spawn_twice_other_name() {
-        let s = match SombraLinux::build("tcp_echo30222",
+        let systemd = FakeSystemd::new("spawn_twice_other_name");
+        let s = match SombraLinux::build("tcp_echo30303",
                                      "executables/tcp_echo",
-                                     vec!["-p".to_string(), "30222".to_string()]) {
-            Ok(s) => s,
+                                     vec!["-p".to_string(), "30303".to_string()]) {
+            Ok(s) => systemd.manage(s),
             Err(e) => panic!(e.to_string()),
         };
         assert_eq!(s.create(), Ok(()));
 
-        match echo_check("127.0.0.1:30222", b"sombra30222") {
+        match echo_check("127.0.0.1:30303", b"sombra30303") {
             Ok(_) => {
-                let s2 = match SombraLinux::build("tcp_echo30223",
+                let s2 = match SombraLinux::build("tcp_echo30304",
                                               "executables/tcp_echo",
-                                              vec!["-p".to_string(), "30223".to_string()]) {
-                    Ok(s) => s,
+                                              vec!["-p".to_string(), "30304".to_string()]) {
+                    Ok(s) => systemd.manage(s),
                     Err(e) => panic!(e.to_string()),
                 };
                 assert_eq!(s2.create(), Ok(()));
-                match echo_check("127.0.0.1:30223", b"sombra30223") {
+                match echo_check("127.0.0.1:30304", b"sombra30304") {
                     Ok(_) => {
                         assert_eq!(s.delete(), Ok(()));
                         assert_eq!(s2.delete(), Ok(()));
@@ -210,14 +694,15 @@ mod tests {
 
     #[test]
     fn spawn_with_args() {
+        let systemd = FakeSystemd::new("spawn_with_args");
         let s = match SombraLinux::build("tcp_echo",
                                      "executables/tcp_echo",
-                                     vec!["-p".to_string(), "30223".to_string()]) {
-            Ok(s) => s,
+                                     vec!["-p".to_string(), "30305".to_string()]) {
+            Ok(s) => systemd.manage(s),
             Err(e) => panic!(e.to_string()),
         };
         assert_eq!(s.create(), Ok(()));
-        let res = echo_check("127.0.0.1:30223", b"sombra30223");
+        let res = echo_check("127.0.0.1:30305", b"sombra30305");
         assert_eq!(s.delete(), Ok(()));
         if let Err(e) = res {
             panic!(format!("{:?}", e));
@@ -226,12 +711,14 @@ mod tests {
 
     #[test]
     fn spawn_once_delete_twice() {
-        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo", vec![]) {
-            Ok(s) => s,
+        let systemd = FakeSystemd::new("spawn_once_delete_twice");
+        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                     vec!["-p".to_string(), "30306".to_string()]) {
+            Ok(s) => systemd.manage(s),
             Err(e) => panic!(e.to_string()),
         };
         assert_eq!(s.create(), Ok(()));
-        match echo_check("127.0.0.1:30222", b"sombra30222") {
+        match echo_check("127.0.0.1:30306", b"sombra30306") {
             Ok(_) => {
                 assert_eq!(s.delete(), Ok(()));
                 assert_ne!(s.delete(), Ok(()));
@@ -245,16 +732,18 @@ mod tests {
 
     #[test]
     fn spawn_bug_and_correct() {
-        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo", vec![]) {
-            Ok(s) => s,
+        let systemd = FakeSystemd::new("spawn_bug_and_correct");
+        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                     vec!["-p".to_string(), "30307".to_string()]) {
+            Ok(s) => systemd.manage(s),
             Err(e) => panic!(e.to_string()),
         };
         assert_eq!(s.create(), Ok(()));
-        match echo_check("127.0.0.1:30222", b"bug") {
+        match echo_check("127.0.0.1:30307", b"bug") {
             Ok(_) => {
                 assert_eq!(s.delete(), Ok(()));
                 assert_eq!(s.create(), Ok(()));
-                match echo_check("127.0.0.1:30222", b"sombra30222") {
+                match echo_check("127.0.0.1:30307", b"sombra30307") {
                     Ok(_) => {
                         assert_eq!(s.delete(), Ok(()));
                     },
@@ -270,7 +759,548 @@ mod tests {
             }
         }
     }
-}
 
-// Run test on linux as sudo
-// sudo -E cargo test -- --test-threads 1
\ No newline at end of file
+    fn props(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn parse_status_states() {
+        let now = Duration::from_secs(100);
+        let running = SombraLinux::parse_status(&props(&[
+            ("LoadState", "loaded"), ("ActiveState", "active"), ("SubState", "running"),
+            ("MainPID", "42"), ("ActiveEnterTimestampMonotonic", "40000000"),
+            ("ExecMainCode", "0"), ("ExecMainStatus", "0")]), now);
+        assert_eq!(running.state, ServiceState::Running);
+        assert_eq!(running.main_pid, Some(42));
+        assert_eq!(running.uptime, Some(Duration::from_secs(60)));
+        assert_eq!(running.last_exit_code, None);
+
+        let failed = SombraLinux::parse_status(&props(&[
+            ("LoadState", "loaded"), ("ActiveState", "failed"), ("SubState", "failed"),
+            ("MainPID", "0"), ("ExecMainCode", "1"), ("ExecMainStatus", "3")]), now);
+        assert_eq!(failed.state, ServiceState::Failed);
+        assert_eq!(failed.main_pid, None);
+        assert_eq!(failed.last_exit_code, Some(3));
+
+        let killed = SombraLinux::parse_status(&props(&[
+            ("LoadState", "loaded"), ("ActiveState", "inactive"), ("SubState", "dead"),
+            ("ExecMainCode", "2"), ("ExecMainStatus", "15")]), now);
+        assert_eq!(killed.state, ServiceState::Stopped);
+        assert_eq!(killed.last_exit_code, Some(143));
+
+        let missing = SombraLinux::parse_status(&props(&[
+            ("LoadState", "not-found"), ("ActiveState", "inactive")]), now);
+        assert_eq!(missing.state, ServiceState::NotInstalled);
+    }
+
+    #[test]
+    fn status_follows_lifecycle() {
+        let systemd = FakeSystemd::new("status_follows_lifecycle");
+        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                     vec!["-p".to_string(), "30308".to_string()]) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(s.status().map(|st| st.state), Ok(ServiceState::NotInstalled));
+        assert_eq!(s.create(), Ok(()));
+        let status = s.status();
+        assert_eq!(s.delete(), Ok(()));
+        match status {
+            Ok(status) => {
+                assert_eq!(status.state, ServiceState::Running);
+                assert!(status.main_pid.is_some());
+            },
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn stop_and_start_keep_unit() {
+        let systemd = FakeSystemd::new("stop_and_start_keep_unit");
+        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                     vec!["-p".to_string(), "30309".to_string()]) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(s.create(), Ok(()));
+        assert_eq!(s.stop(), Ok(()));
+        let stopped = s.status().map(|st| st.state);
+        assert_eq!(s.start(), Ok(()));
+        let res = echo_check("127.0.0.1:30309", b"sombra30309");
+        assert_eq!(s.delete(), Ok(()));
+        assert_eq!(stopped, Ok(ServiceState::Stopped));
+        if let Err(e) = res {
+            panic!("{:?}", e);
+        }
+    }
+
+    #[test]
+    fn create_disabled_does_not_start() {
+        let systemd = FakeSystemd::new("create_disabled_does_not_start");
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .startup(StartupMode::Disabled);
+        let s = match SombraLinux::from_spec(spec) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(s.create(), Ok(()));
+        let state = s.status().map(|st| st.state);
+        assert_eq!(s.delete(), Ok(()));
+        assert_eq!(state, Ok(ServiceState::Stopped));
+    }
+
+    #[test]
+    fn create_fails_when_not_active() {
+        let systemd = FakeSystemd::new("create_fails_when_not_active");
+        let s = match SombraLinux::build("sombra_false", "/bin/false", vec![]) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        let res = s.create();
+        assert_eq!(s.delete(), Ok(()));
+        match res {
+            Err(e) => assert_eq!(e.kind(), &crate::ErrorKind::ServiceManager),
+            Ok(()) => panic!("created a service that exited"),
+        }
+    }
+
+    #[test]
+    fn marker_round_trip() {
+        let spec = ServiceSpec::new("tcp_echo", "/usr/bin/python3")
+            .args(vec!["/opt/tcp_echo.py".to_string(), "-p".to_string()])
+            .description("TCP echo written in python")
+            .startup(StartupMode::Boot);
+        let marker = Marker::new(&spec);
+        let unit = match SombraLinux::service_file_content(&marker) {
+            Ok(unit) => unit,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(unit.contains("Description=TCP echo written in python\n"));
+        assert_eq!(marker::parse("tcp_echo", &unit), Some(marker));
+        assert_eq!(marker::parse("tcp_echo", "[Unit]\nDescription=distro unit\n"), None);
+    }
+
+    #[test]
+    fn environment_is_escaped() {
+        let spec = ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo")
+            .env("GREETING", "say \"hi\" to %u\nthen $LEAVE \\o/")
+            .env("EMPTY", "");
+        let marker = Marker::new(&spec);
+        let unit = match SombraLinux::service_file_content(&marker) {
+            Ok(unit) => unit,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(unit.contains("Environment=\"GREETING=say \\\"hi\\\" to %%u\\nthen $LEAVE \\\\o/\"\n"));
+        assert!(unit.contains("Environment=\"EMPTY=\"\n"));
+        assert_eq!(marker::parse("tcp_echo", &unit), Some(marker));
+    }
+
+    #[test]
+    fn env_file_is_merged() {
+        let env_file = std::env::temp_dir().join("sombra_env_file_is_merged.env");
+        std::fs::write(&env_file, "PORT=30222\nexport HOST='0.0.0.0'\n").unwrap();
+        let spec = ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo")
+            .env_file(env_file.to_str().unwrap())
+            .env("PORT", "30223");
+        let unit = SombraLinux::service_file_content(&Marker::new(&spec));
+        std::fs::remove_file(&env_file).unwrap();
+        match unit {
+            Ok(unit) => {
+                assert!(unit.contains("Environment=\"HOST=0.0.0.0\"\n"));
+                assert!(unit.contains("Environment=\"PORT=30223\"\n"));
+                assert!(!unit.contains("PORT=30222"));
+            },
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn working_directory_from_executable() {
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .working_directory_from_executable();
+        let s = match SombraLinux::from_spec(spec) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        };
+        let unit = SombraLinux::service_file_content(&Marker::new(&s.spec));
+        let executables = dunce::canonicalize("executables").unwrap();
+        match unit {
+            Ok(unit) => assert!(unit.contains(&format!("WorkingDirectory={}\n",
+                                                       executables.display()))),
+            Err(e) => panic!("{}", e),
+        }
+
+        let missing = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .working_directory("executables/missing");
+        assert!(SombraLinux::from_spec(missing).is_err());
+        let file = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .working_directory("executables/tcp_echo");
+        assert!(SombraLinux::from_spec(file).is_err());
+    }
+
+    #[test]
+    fn restart_policy_directives() {
+        let spec = ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo")
+            .restart(RestartPolicy::new(RestartMode::OnFailure)
+                .delay(Duration::from_millis(500))
+                .burst(5, Duration::from_secs(60))
+                .backoff(Duration::from_secs(30), 4)
+                .success_exit_code(143)
+                .prevent_exit_code(2)
+                .prevent_exit_code(3));
+        let marker = Marker::new(&spec);
+        let unit = match SombraLinux::service_file_content(&marker) {
+            Ok(unit) => unit,
+            Err(e) => panic!("{}", e),
+        };
+        for directive in &["StartLimitIntervalSec=60000ms\n", "StartLimitBurst=5\n",
+                           "Restart=on-failure\n", "RestartSec=500ms\n", "RestartSteps=4\n",
+                           "RestartMaxDelaySec=30000ms\n", "SuccessExitStatus=143\n",
+                           "RestartPreventExitStatus=2 3\n"] {
+            assert!(unit.contains(directive), "missing {}", directive);
+        }
+        assert!(!unit.contains("StartLimitIntervalSec=0\n"));
+        assert_eq!(marker::parse("tcp_echo", &unit), Some(marker));
+    }
+
+    #[test]
+    fn restart_always_recovers() {
+        let systemd = FakeSystemd::new("restart_always_recovers");
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .args(vec!["-p".to_string(), "30310".to_string()])
+            .restart(RestartPolicy::new(RestartMode::Always)
+                .delay(Duration::from_millis(100)));
+        let s = match SombraLinux::from_spec(spec) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(s.create(), Ok(()));
+        let _ = echo_check("127.0.0.1:30310", b"bug");
+        std::thread::sleep(Duration::from_millis(500));
+        let res = echo_check("127.0.0.1:30310", b"sombra30310");
+        assert_eq!(s.delete(), Ok(()));
+        if let Err(e) = res {
+            panic!("{:?}", e);
+        }
+    }
+
+    #[test]
+    fn run_as_directives() {
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .user("nobody")
+            .group("root")
+            .supplementary_group("root")
+            .ambient_capability("net_bind_service");
+        let s = match SombraLinux::from_spec(spec) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        };
+        let marker = Marker::new(&s.spec);
+        let unit = match SombraLinux::service_file_content(&marker) {
+            Ok(unit) => unit,
+            Err(e) => panic!("{}", e),
+        };
+        for directive in &["User=nobody\n", "Group=root\n", "SupplementaryGroups=root\n",
+                           "AmbientCapabilities=CAP_NET_BIND_SERVICE\n"] {
+            assert!(unit.contains(directive), "missing {}", directive);
+        }
+        assert_eq!(marker::parse("tcp_echo", &unit), Some(marker));
+    }
+
+    #[test]
+    fn unknown_account_is_rejected() {
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo");
+        assert!(SombraLinux::from_spec(spec.clone().user("sombra-no-such-user")).is_err());
+        assert!(SombraLinux::from_spec(spec.clone().group("sombra-no-such-group")).is_err());
+        assert!(SombraLinux::from_spec(spec.ambient_capability("net bind")).is_err());
+    }
+
+    // Splits an ExecStart= line back into words, the way systemd does: specifiers are
+    // resolved first, then words are unquoted and finally `$$` is unescaped
+    fn exec_start_words(unit: &str) -> Vec<String> {
+        let line = unit.lines()
+            .find_map(|line| line.strip_prefix("ExecStart="))
+            .expect("unit without ExecStart=")
+            .replace("%%", "%");
+        let mut words = vec![];
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                ' ' => continue,
+                '\\' => {
+                    assert_eq!(chars.next(), Some(';'));
+                    words.push(";".to_string());
+                    continue;
+                },
+                '"' => (),
+                other => panic!("unquoted {:?} in {}", other, line),
+            }
+            let mut word = String::new();
+            loop {
+                match chars.next().expect("unterminated quote") {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('\\') => word.push('\\'),
+                        Some('"') => word.push('"'),
+                        other => panic!("unexpected escape {:?}", other),
+                    },
+                    c => word.push(c),
+                }
+            }
+            words.push(word.replace("$$", "$"));
+        }
+        words
+    }
+
+    fn exec_start_round_trip(executable: &str, args: Vec<String>) -> Vec<String> {
+        let spec = ServiceSpec::new("tcp_echo", executable).args(args);
+        match SombraLinux::service_file_content(&Marker::new(&spec)) {
+            Ok(unit) => exec_start_words(&unit),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn exec_start_is_quoted() {
+        let args: Vec<String> = vec!["-p", "30 222", "\"quoted\"", "it's", "%n", "$HOME", "${PATH}",
+                                     "back\\slash", ";", "", "[Service]"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let words = exec_start_round_trip("/opt/tcp echo/tcp_echo", args.clone());
+        assert_eq!(words[0], "/opt/tcp echo/tcp_echo");
+        assert_eq!(words[1..].to_vec(), args);
+    }
+
+    #[test]
+    fn exec_start_rejects_control_characters() {
+        for arg in &["30222\nExecStartPre=/bin/rm", "tab\there", "bell\u{7}", "next\u{85}line"] {
+            let spec = ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo").arg(arg);
+            assert!(SombraLinux::service_file_content(&Marker::new(&spec)).is_err(), "{:?}", arg);
+        }
+    }
+
+    #[test]
+    fn unit_rejects_control_characters() {
+        let spec = ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo");
+        let injected = "echo\n[Service]\nExecStartPre=/bin/rm";
+        for spec in &[spec.clone().description(injected),
+                      spec.clone().root("/tmp").user(injected),
+                      spec.clone().group(injected),
+                      spec.clone().env_file(injected)] {
+            assert!(SombraLinux::service_file_content(&Marker::new(spec)).is_err(), "{:?}", spec);
+        }
+
+        let marker = Marker::new(&spec.description("50% off"));
+        let unit = match SombraLinux::service_file_content(&marker) {
+            Ok(unit) => unit,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(unit.starts_with("[Unit]\nDescription=50%% off\n"), "{}", unit);
+        assert_eq!(marker::parse("tcp_echo", &unit), Some(marker));
+    }
+
+    proptest! {
+        #[test]
+        fn exec_start_round_trips(executable in "/\\PC*",
+                                  args in prop::collection::vec("\\PC*", 0..8)) {
+            let mut expected = vec![executable.clone()];
+            expected.extend(args.iter().cloned());
+            prop_assert_eq!(exec_start_round_trip(&executable, args), expected);
+        }
+    }
+
+    #[test]
+    fn readiness_marker_round_trip() {
+        let spec = ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo")
+            .readiness(Readiness::new(Probe::Command(PathBuf::from("/usr/bin/curl"),
+                                                     vec!["-f".to_string(), "a b".to_string()]))
+                .timeout(Duration::from_secs(5)));
+        let marker = Marker::new(&spec);
+        let unit = match SombraLinux::service_file_content(&marker) {
+            Ok(unit) => unit,
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(marker::parse("tcp_echo", &unit), Some(marker));
+    }
+
+    #[test]
+    fn log_files_directives() {
+        let spec = ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo")
+            .log_files(LogFiles::new("/var/log/tcp echo.log")
+                .stderr("/var/log/tcp_echo.err")
+                .truncate()
+                .rotate(1 << 20, 3));
+        let marker = Marker::new(&spec);
+        let unit = match SombraLinux::service_file_content(&marker) {
+            Ok(unit) => unit,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(unit.contains("StandardOutput=truncate:/var/log/tcp echo.log\n"), "{}", unit);
+        assert!(unit.contains("StandardError=truncate:/var/log/tcp_echo.err\n"), "{}", unit);
+        assert_eq!(marker::parse("tcp_echo", &unit), Some(marker));
+    }
+
+    #[test]
+    fn user_unit_has_no_account() {
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo").scope(Scope::User);
+        let s = match SombraLinux::from_spec(spec.clone()) {
+            Ok(s) => s,
+            Err(e) => panic!("{}", e),
+        };
+        let unit = match SombraLinux::service_file_content(&Marker::new(&s.spec)) {
+            Ok(unit) => unit,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(!unit.contains("User="), "{}", unit);
+        assert!(unit.contains("WantedBy=default.target\n"), "{}", unit);
+        assert!(s.unit_path().ends_with("systemd/user/tcp_echo.service"));
+
+        assert!(SombraLinux::from_spec(spec.clone().user("nobody")).is_err());
+        assert!(SombraLinux::from_spec(spec.linger()).is_ok());
+        let system = ServiceSpec::new("tcp_echo", "executables/tcp_echo").linger();
+        assert!(SombraLinux::from_spec(system).is_err());
+    }
+
+    #[test]
+    fn privileges_follow_effective_uid() {
+        assert!(account::is_privileged(&std::env::temp_dir()));
+        if unsafe { libc::geteuid() } != 0 {
+            assert!(!account::is_privileged(Path::new("/proc")));
+            let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo", vec![]) {
+                Ok(s) => s,
+                Err(e) => panic!("{}", e),
+            };
+            let err = s.start().expect_err("started without privileges");
+            assert_eq!(err.kind(), &crate::ErrorKind::PermissionDenied);
+        }
+    }
+
+    #[test]
+    fn install_into_root() {
+        let root = std::env::temp_dir().join(format!("sombra-root-{}", std::process::id()));
+        let _ = std::fs::create_dir_all(root.join("usr/bin"));
+        let _ = std::fs::write(root.join("usr/bin/tcp_echo"), "");
+        let unit = root.join("etc/systemd/system/tcp_echo.service");
+        let wants = root.join("etc/systemd/system/multi-user.target.wants/tcp_echo.service");
+
+        let spec = ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo")
+            .startup(StartupMode::Boot)
+            .root(&root.display().to_string());
+        let s = SombraLinux::from_spec(spec.clone());
+        let created = s.as_ref().map_err(|e| e.to_string()).and_then(|s| {
+            s.create().map_err(|e| e.to_string())?;
+            let content = std::fs::read_to_string(&unit).map_err(|e| e.to_string())?;
+            let link = std::fs::read_link(&wants).map_err(|e| e.to_string())?;
+            let started = s.start().map_err(|e| *e.kind() == crate::ErrorKind::Unsupported);
+            s.disable().map_err(|e| e.to_string())?;
+            let disabled = !wants.exists();
+            s.delete().map_err(|e| e.to_string())?;
+            Ok((content, link, started, disabled, unit.exists()))
+        });
+        let missing = SombraLinux::from_spec(ServiceSpec::new("tcp_echo", "/usr/bin/no_such")
+            .root(&root.display().to_string()));
+        let _ = std::fs::remove_dir_all(&root);
+
+        let (content, link, started, disabled, remains) = match created {
+            Ok(created) => created,
+            Err(e) => panic!("{}", e),
+        };
+        assert!(content.contains("ExecStart=\"/usr/bin/tcp_echo\"\n"), "{}", content);
+        assert!(!content.contains("User="), "{}", content);
+        assert_eq!(link, PathBuf::from("/etc/systemd/system/tcp_echo.service"));
+        assert_eq!(started, Err(true));
+        assert!(disabled);
+        assert!(!remains);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn create_waits_for_readiness() {
+        let systemd = FakeSystemd::new("create_waits_for_readiness");
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .args(vec!["-p".to_string(), "30311".to_string()])
+            .readiness(Readiness::new(Probe::Tcp("127.0.0.1:30311".to_string())));
+        let s = match SombraLinux::from_spec(spec) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(s.create(), Ok(()));
+        let res = TcpStream::connect("127.0.0.1:30311");
+        assert_eq!(s.delete(), Ok(()));
+        if let Err(e) = res {
+            panic!("{:?}", e);
+        }
+    }
+
+    #[test]
+    fn monitor_stops_unhealthy() {
+        let systemd = FakeSystemd::new("monitor_stops_unhealthy");
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .args(vec!["-p".to_string(), "30312".to_string()])
+            .liveness(Liveness::new(Probe::Tcp("127.0.0.1:30999".to_string()))
+                .interval(Duration::from_millis(100))
+                .threshold(2)
+                .action(HealthAction::Stop));
+        let s = match SombraLinux::from_spec(spec) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(s.create(), Ok(()));
+        let health_dir = systemd.dir.join("health");
+        let liveness = s.spec.liveness.clone().unwrap();
+        let failures = Monitor::probe(&health_dir, &s, &liveness, 0);
+        let health = crate::health::load(&health_dir, "tcp_echo");
+        let stopped = Monitor::probe(&health_dir, &s, &liveness, 1);
+        let state = s.status().map(|status| status.state);
+        assert_eq!(s.delete(), Ok(()));
+
+        assert_eq!(failures, Ok(1));
+        assert_eq!(stopped, Ok(0));
+        assert_eq!(health.map(|health| (health.healthy, health.consecutive_failures)),
+                   Some((false, 1)));
+        assert_eq!(state, Ok(ServiceState::Stopped));
+    }
+
+    #[test]
+    fn logs_contain_echoed_message() {
+        let systemd = FakeSystemd::new("logs_contain_echoed_message");
+        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                     vec!["-p".to_string(), "30313".to_string()]) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(s.create(), Ok(()));
+        let res = echo_check("127.0.0.1:30313", b"sombra_logs");
+        std::thread::sleep(Duration::from_millis(100));
+        let lines: crate::Result<Vec<_>> = s.logs(&LogQuery::new().lines(10))
+            .and_then(|logs| logs.collect());
+        assert_eq!(s.delete(), Ok(()));
+        if let Err(e) = res {
+            panic!("{:?}", e);
+        }
+        match lines {
+            Ok(lines) => assert!(lines.iter().any(|line| line.message.contains("sombra_logs"))),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn list_contains_created() {
+        let systemd = FakeSystemd::new("list_contains_created");
+        let s = match SombraLinux::build("tcp_echo", "executables/tcp_echo",
+                                     vec!["-p".to_string(), "30314".to_string()]) {
+            Ok(s) => systemd.manage(s),
+            Err(e) => panic!("{}", e),
+        };
+        assert_eq!(s.create(), Ok(()));
+        let listed = SombraLinux::list_units(Scope::System, &systemd.dir);
+        assert_eq!(s.delete(), Ok(()));
+        match listed {
+            Ok(listed) => assert!(listed.iter().any(|l| l.name() == "tcp_echo"
+                && l.executable() == s.executable())),
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use crate::{Sombra, LogFiles, LogQuery, Logs, Readiness, Scope, ServiceSpec, ServiceState,
+            ServiceStatus, StartupMode};
+use crate::linux::account;
+use crate::linux::marker;
+use crate::marker::Marker;
+use crate::error::ErrorKind::Other;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const SYSTEM_STATE_DIR: &str = "/var/lib/sombra";
+// Output files used when the spec has none, rotated so they cannot fill the disk
+const LOG_MAX_SIZE: u64 = 10 << 20;
+const LOG_RETAIN: u32 = 5;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Time a service has to exit after SIGTERM before the daemon sends SIGKILL
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Directory of the built-in supervisor: the specs, the default output files and the
+/// socket of `sombra daemon`
+#[derive(Debug, Clone)]
+pub struct StateDir(pub PathBuf);
+
+impl StateDir {
+    pub fn new(scope: Scope) -> crate::Result<Self> {
+        if let Some(dir) = crate::linux::test_hook("SOMBRA_SUPERVISOR_DIR") {
+            return Ok(StateDir(dir));
+        }
+        if scope == Scope::System {
+            return Ok(StateDir(PathBuf::from(SYSTEM_STATE_DIR)));
+        }
+        match (std::env::var_os("XDG_STATE_HOME"), std::env::var_os("HOME")) {
+            (Some(state), _) if !state.is_empty() => Ok(StateDir(Path::new(&state).join("sombra"))),
+            (_, Some(home)) => Ok(StateDir(Path::new(&home).join(".local/state/sombra"))),
+            _ => Err(crate::Error::new(Other, "HOME is not set, cannot find the user state \
+                                              directory".to_string())),
+        }
+    }
+
+    pub fn services(&self) -> PathBuf {
+        self.0.join("services")
+    }
+
+    pub fn spec_path(&self, name: &str) -> PathBuf {
+        self.services().join(format!("{}.service", name))
+    }
+
+    pub fn socket(&self) -> PathBuf {
+        self.0.join("sombra.sock")
+    }
+
+    /// Output files of the service, `logs/<name>.log` when the spec has none
+    pub fn log_files(&self, spec: &ServiceSpec) -> LogFiles {
+        match &spec.log_files {
+            Some(log_files) => log_files.clone(),
+            None => LogFiles {
+                stdout: self.0.join("logs").join(format!("{}.log", spec.name)),
+                ..LogFiles::new("").rotate(LOG_MAX_SIZE, LOG_RETAIN)
+            },
+        }
+    }
+
+    /// Spec stored for the service, `None` when it does not exist
+    pub fn read_spec(&self, scope: Scope, name: &str) -> crate::Result<Option<ServiceSpec>> {
+        let content = match std::fs::read_to_string(self.spec_path(name)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(crate::Error::from(e)
+                .content(self.spec_path(name).display().to_string())),
+        };
+        Ok(marker::parse(name, &content).map(|marker| ServiceSpec { scope, ..marker.spec }))
+    }
+
+    /// Names of the stored services, sorted
+    pub fn names(&self) -> crate::Result<Vec<String>> {
+        let entries = match std::fs::read_dir(self.services()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut names = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "service") {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Sends one command to the daemon and returns the `key=value` lines of its answer,
+    /// `None` when the daemon is not running
+    pub fn request(&self, command: &str, name: &str)
+                   -> crate::Result<Option<HashMap<String, String>>> {
+        let socket = self.socket();
+        let mut stream = match UnixStream::connect(&socket) {
+            Ok(stream) => stream,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound
+                || e.kind() == std::io::ErrorKind::ConnectionRefused => return Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                return Err(crate::Error::new(crate::ErrorKind::PermissionDenied, e.to_string())
+                    .content(socket.display().to_string()));
+            },
+            Err(e) => return Err(crate::Error::from(e).content(socket.display().to_string())),
+        };
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        stream.write_all(format!("{} {}\n", command, name).as_bytes())?;
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer)?;
+
+        let mut lines = answer.lines();
+        match lines.next() {
+            Some("ok") => Ok(Some(lines
+                .filter_map(|line| {
+                    let mut pair = line.splitn(2, '=');
+                    Some((pair.next()?.to_string(), pair.next()?.to_string()))
+                })
+                .collect())),
+            Some(line) if line.starts_with("err ") => Err(crate::Error::new(
+                crate::ErrorKind::ServiceManager, line[4..].to_string())
+                .content(format!("sombra daemon {} {}", command, name))),
+            _ => Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                       "Unexpected answer of sombra daemon".to_string())
+                .content(socket.display().to_string())),
+        }
+    }
+}
+
+/// Service run by the built-in supervisor, `sombra daemon`, for systems without systemd
+pub struct SombraSupervisor {
+    spec: ServiceSpec,
+    state_dir: StateDir,
+}
+
+impl SombraSupervisor {
+    fn parse_status(values: &HashMap<String, String>) -> ServiceStatus {
+        let value = |key: &str| values.get(key).map(String::as_str).unwrap_or("");
+        let state = match value("state") {
+            "running" => ServiceState::Running,
+            "failed" => ServiceState::Failed,
+            _ => ServiceState::Stopped,
+        };
+        let mut status = ServiceStatus::new(state);
+        status.main_pid = value("pid").parse().ok();
+        status.uptime = value("uptime_ms").parse().ok().map(Duration::from_millis);
+        status.last_exit_code = value("exit_code").parse().ok();
+        status
+    }
+
+    fn request(&self, command: &str) -> crate::Result<Option<HashMap<String, String>>> {
+        self.state_dir.request(command, &self.spec.name)
+    }
+
+    // Commands that run something need the daemon
+    fn request_running(&self, command: &str) -> crate::Result<HashMap<String, String>> {
+        match self.request(command)? {
+            Some(values) => Ok(values),
+            None => Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                          "sombra daemon is not running, start it with \
+                                           `sombra daemon`".to_string())
+                .content(self.state_dir.socket().display().to_string())),
+        }
+    }
+
+    fn stored_spec(&self) -> crate::Result<Option<ServiceSpec>> {
+        self.state_dir.read_spec(self.spec.scope, &self.spec.name)
+    }
+
+    // Services managed by name keep the output files they were created with
+    fn log_files(&self) -> LogFiles {
+        if self.spec.log_files.is_none() {
+            if let Ok(Some(stored)) = self.stored_spec() {
+                return self.state_dir.log_files(&stored);
+            }
+        }
+        self.state_dir.log_files(&self.spec)
+    }
+
+    // Starts the service, then waits for the readiness probe reading the output files
+    fn launch(&self, readiness: Option<&Readiness>) -> crate::Result<()> {
+        let log_files = self.log_files();
+        let sizes = log_files.sizes();
+        self.request_running("start")?;
+        match readiness {
+            Some(readiness) => readiness.wait(&self.spec.name, || log_files.output_since(&sizes)),
+            None => Ok(()),
+        }
+    }
+
+    // The daemon signals the process, it is gone once the daemon reaped it
+    fn wait_stopped(&self) -> crate::Result<()> {
+        let deadline = Instant::now() + STOP_TIMEOUT + Duration::from_secs(1);
+        while Instant::now() < deadline {
+            match self.request("status")? {
+                Some(values) if values.get("state").map(String::as_str) == Some("running") => (),
+                _ => return Ok(()),
+            }
+            std::thread::sleep(STOP_POLL_INTERVAL);
+        }
+        Err(crate::Error::new(crate::ErrorKind::Timeout,
+                              format!("Service {} did not stop", self.spec.name)))
+    }
+
+    fn set_startup(&self, startup: StartupMode) -> crate::Result<()> {
+        let path = self.state_dir.spec_path(&self.spec.name);
+        let content = std::fs::read_to_string(&path)?;
+        let mut marker = marker::parse(&self.spec.name, &content)
+            .ok_or_else(|| self.not_managed())?;
+        marker.spec.startup = startup;
+        Ok(std::fs::write(&path, marker::render(&marker))?)
+    }
+
+    fn remove(&self, force: bool) -> crate::Result<()> {
+        self.check_privileges("Deleting")?;
+        self.check_exists()?;
+        if !force && self.stored_spec()?.is_none() {
+            return Err(self.not_managed());
+        }
+        self.stop()?;
+        Ok(std::fs::remove_file(self.state_dir.spec_path(&self.spec.name))?)
+    }
+
+    fn not_managed(&self) -> crate::Error {
+        crate::Error::new(crate::ErrorKind::NotManaged,
+                          format!("Service {} was not created by sombra", self.spec.name))
+    }
+
+    fn check_exists(&self) -> crate::Result<()> {
+        if self.state_dir.spec_path(&self.spec.name).exists() {
+            Ok(())
+        } else {
+            Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} does not exist",
+                                                                self.spec.name)))
+        }
+    }
+
+    // The state directory is created on demand, so its closest existing ancestor decides
+    fn check_privileges(&self, operation: &str) -> crate::Result<()> {
+        let services = self.state_dir.services();
+        let existing = services.ancestors()
+            .find(|dir| dir.exists())
+            .unwrap_or_else(|| Path::new("/"));
+        if account::is_privileged(existing) {
+            return Ok(());
+        }
+        Err(crate::Error::new(crate::ErrorKind::PermissionDenied,
+                              format!("{} service {} needs root privileges",
+                                      operation, self.spec.name))
+            .content(services.display().to_string()))
+    }
+}
+
+impl Sombra for SombraSupervisor {
+    fn from_spec(spec: ServiceSpec) -> crate::Result<Self> {
+        let spec = spec.canonicalize()?;
+        if spec.root.is_some() {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "The supervisor cannot install into a root directory"
+                                             .to_string()));
+        }
+        if !spec.ambient_capabilities.is_empty() || spec.linger {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "The supervisor grants no capabilities and does not \
+                                          linger".to_string()));
+        }
+        if spec.scope == Scope::User && (spec.user.is_some() || spec.group.is_some()
+            || !spec.supplementary_groups.is_empty()) {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "User services run as the current user, without \
+                                          other accounts or groups".to_string()));
+        }
+        if let Some(user) = &spec.user {
+            account::user(user)?;
+        }
+        for group in spec.group.iter().chain(&spec.supplementary_groups) {
+            account::group(group)?;
+        }
+
+        Ok(SombraSupervisor {
+            state_dir: StateDir::new(spec.scope)?,
+            spec,
+        })
+    }
+
+    fn list_in(scope: Scope) -> crate::Result<Vec<Self>> {
+        let state_dir = StateDir::new(scope)?;
+        let mut services = vec![];
+        for name in state_dir.names()? {
+            if let Some(spec) = state_dir.read_spec(scope, &name)? {
+                services.push(SombraSupervisor { spec, state_dir: state_dir.clone() });
+            }
+        }
+        Ok(services)
+    }
+
+    fn spec(&self) -> &ServiceSpec {
+        &self.spec
+    }
+
+    fn create(&self) -> crate::Result<()> {
+        self.check_privileges("Creating")?;
+
+        let path = self.state_dir.spec_path(&self.spec.name);
+        if path.exists() {
+            return Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} already exist",
+                                         self.spec.name)));
+        }
+        std::fs::create_dir_all(self.state_dir.services())?;
+        std::fs::write(&path, marker::render(&Marker::new(&self.spec)))?;
+
+        if self.spec.startup == StartupMode::Disabled {
+            return Ok(());
+        }
+        self.launch(self.spec.readiness.as_ref())?;
+        if self.spec.readiness.is_some() {
+            return Ok(());
+        }
+
+        // Without a probe, give the process a moment to fail
+        std::thread::sleep(Duration::from_millis(100));
+        let status = self.status()?;
+        if !status.is_running() {
+            return Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                         format!("Service {} is {} after start, expected running",
+                                                 self.spec.name, status.state))
+                .content(format!("sombra daemon status {}", self.spec.name)));
+        }
+        Ok(())
+    }
+
+    fn delete(&self) -> crate::Result<()> {
+        self.remove(false)
+    }
+
+    fn force_delete(&self) -> crate::Result<()> {
+        self.remove(true)
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        self.check_privileges("Starting")?;
+        self.check_exists()?;
+        let readiness = match &self.spec.readiness {
+            Some(readiness) => Some(readiness.clone()),
+            None => self.stored_spec()?.and_then(|spec| spec.readiness),
+        };
+        self.launch(readiness.as_ref())
+    }
+
+    fn stop(&self) -> crate::Result<()> {
+        self.check_privileges("Stopping")?;
+        self.check_exists()?;
+        // Nothing runs without the daemon
+        match self.request("stop")? {
+            Some(_) => self.wait_stopped(),
+            None => Ok(()),
+        }
+    }
+
+    fn enable(&self) -> crate::Result<()> {
+        self.check_privileges("Enabling")?;
+        self.check_exists()?;
+        self.set_startup(StartupMode::Boot)
+    }
+
+    fn disable(&self) -> crate::Result<()> {
+        self.check_privileges("Disabling")?;
+        self.check_exists()?;
+        self.set_startup(StartupMode::Manual)
+    }
+
+    fn status(&self) -> crate::Result<ServiceStatus> {
+        if !self.state_dir.spec_path(&self.spec.name).exists() {
+            return Ok(ServiceStatus::new(ServiceState::NotInstalled));
+        }
+        let mut status = match self.request("status")? {
+            Some(values) => SombraSupervisor::parse_status(&values),
+            None => ServiceStatus::new(ServiceState::Stopped),
+        };
+        if status.is_running() {
+            status.health = crate::health::load(&crate::health::health_dir(self.spec.scope),
+                                                &self.spec.name);
+        }
+        Ok(status)
+    }
+
+    fn logs(&self, query: &LogQuery) -> crate::Result<Logs> {
+        self.log_files().logs(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status_values() {
+        let values: HashMap<String, String> = [("state", "running"), ("pid", "42"),
+                                               ("uptime_ms", "1500"), ("exit_code", "3")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        let status = SombraSupervisor::parse_status(&values);
+        assert_eq!(status.state, ServiceState::Running);
+        assert_eq!(status.main_pid, Some(42));
+        assert_eq!(status.uptime, Some(Duration::from_millis(1500)));
+        assert_eq!(status.last_exit_code, Some(3));
+
+        let failed = SombraSupervisor::parse_status(&[("state".to_string(), "failed".to_string())]
+            .iter().cloned().collect());
+        assert_eq!(failed.state, ServiceState::Failed);
+        assert_eq!(failed.main_pid, None);
+    }
+
+    #[test]
+    fn default_log_files_live_in_state_dir() {
+        let state_dir = StateDir(PathBuf::from("/var/lib/sombra"));
+        let spec = ServiceSpec::new("echo", "/bin/echo");
+        let log_files = state_dir.log_files(&spec);
+        assert_eq!(log_files.stdout, Path::new("/var/lib/sombra/logs/echo.log"));
+        assert_eq!(log_files.max_size, Some(LOG_MAX_SIZE));
+        assert_eq!(state_dir.log_files(&spec.log_files(LogFiles::new("/tmp/echo.log"))),
+                   LogFiles::new("/tmp/echo.log"));
+    }
+}
@@ -1,57 +1,89 @@
+use crate::Scope;
+use crate::linux::tool::{self, Output};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
 pub struct Systemctl {
-    name: String
+    name: String,
+    scope: Scope,
+    program: PathBuf,
 }
 
 impl Systemctl {
-    pub fn new(name: &str) -> Self {
+    pub fn new(name: &str, scope: Scope) -> Self {
+        let program = crate::linux::test_hook("SOMBRA_SYSTEMCTL")
+            .unwrap_or_else(|| PathBuf::from("systemctl"));
+        Systemctl::with_program(name, scope, &program)
+    }
+
+    pub fn with_program(name: &str, scope: Scope, program: &Path) -> Self {
         Systemctl {
-            name: name.to_string()
+            name: name.to_string(),
+            scope,
+            program: program.to_path_buf(),
+        }
+    }
+
+    // Arguments selecting the service manager instance, before the command
+    fn scope_args(&self) -> &'static [&'static str] {
+        match self.scope {
+            Scope::System => &[],
+            Scope::User => &["--user"],
         }
     }
 
+    fn run(&self, args: &[&str]) -> crate::Result<Output> {
+        let args: Vec<&str> = self.scope_args().iter().chain(args).cloned().collect();
+        tool::run(&self.program, "systemctl", &args)
+    }
+
+    fn check(&self, args: &[&str]) -> crate::Result<String> {
+        self.run(args)?.check()
+    }
+
     pub fn start(&self) -> crate::Result<()> {
-        let _ = std::process::Command::new("systemctl")
-            .arg("start")
-            .arg(&self.name)
-            .output()?;
-        Ok(())
+        self.check(&["start", &self.name]).map(|_| ())
     }
 
     pub fn stop(&self) -> crate::Result<()> {
-        let _ = std::process::Command::new("systemctl")
-            .arg("stop")
-            .arg(&self.name)
-            .output()?;
-        Ok(())
+        self.check(&["stop", &self.name]).map(|_| ())
+    }
+
+    pub fn restart(&self) -> crate::Result<()> {
+        self.check(&["restart", &self.name]).map(|_| ())
+    }
+
+    /// State reported by `systemctl is-active`, such as "active" or "failed"
+    pub fn active_state(&self) -> crate::Result<String> {
+        // is-active exits with failure for every state but active
+        let output = self.run(&["is-active", &self.name])?;
+        Ok(output.stdout.trim().to_string())
+    }
+
+    pub fn show(&self, properties: &[&str]) -> crate::Result<HashMap<String, String>> {
+        let properties = format!("--property={}", properties.join(","));
+        let stdout = self.check(&["show", &self.name, &properties])?;
+        Ok(stdout.lines()
+            .filter_map(|line| {
+                let mut pair = line.splitn(2, '=');
+                Some((pair.next()?.to_string(), pair.next()?.to_string()))
+            })
+            .collect())
     }
 
-    pub fn _is_active(&self) -> crate::Result<bool> {
-        let output = std::process::Command::new("systemctl")
-            .arg("is-active")
-            .arg(&self.name)
-            .output()?;
-        Ok(std::str::from_utf8(output.stdout.as_slice())? == "active")
+    pub fn enable(&self) -> crate::Result<()> {
+        self.check(&["enable", &self.name]).map(|_| ())
     }
 
     pub fn disable(&self) -> crate::Result<()> {
-        let _ = std::process::Command::new("systemctl")
-            .arg("disable")
-            .arg(&self.name)
-            .output()?;
-        Ok(())
+        self.check(&["disable", &self.name]).map(|_| ())
     }
 
-    pub fn daemon_reload() -> crate::Result<()> {
-        let _ = std::process::Command::new("systemctl")
-            .arg("daemon-reload")
-            .output()?;
-        Ok(())
+    pub fn daemon_reload(&self) -> crate::Result<()> {
+        self.check(&["daemon-reload"]).map(|_| ())
     }
 
-    pub fn reset_failed() -> crate::Result<()> {
-        let _ = std::process::Command::new("systemctl")
-            .arg("reset-failed")
-            .output()?;
-        Ok(())
+    pub fn reset_failed(&self) -> crate::Result<()> {
+        self.check(&["reset-failed"]).map(|_| ())
     }
 }
@@ -0,0 +1,499 @@
+use crate::{Sombra, LogFiles, LogQuery, Logs, Readiness, RestartMode, Scope, ServiceSpec,
+            ServiceState, ServiceStatus, StartupMode};
+use crate::linux::account;
+use crate::linux::marker;
+use crate::linux::shell;
+use crate::linux::tool;
+use crate::marker::Marker;
+use crate::spec::WorkingDirectory;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const INIT_DIR: &str = "/etc/init.d";
+const PID_DIR: &str = "/run";
+const LOG_DIR: &str = "/var/log";
+// Directories searched for the link tools, init scripts often run with a short PATH
+const SBIN_DIRS: [&str; 2] = ["/usr/sbin", "/sbin"];
+
+// Tool installing the runlevel links of a script
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LinkTool {
+    UpdateRcD,
+    Chkconfig,
+}
+
+impl LinkTool {
+    fn find() -> crate::Result<(LinkTool, PathBuf)> {
+        let mut dirs: Vec<PathBuf> = match std::env::var_os("PATH") {
+            Some(path) => std::env::split_paths(&path).collect(),
+            None => vec![],
+        };
+        dirs.extend(SBIN_DIRS.iter().map(PathBuf::from));
+        for (tool, name) in [(LinkTool::UpdateRcD, "update-rc.d"),
+                             (LinkTool::Chkconfig, "chkconfig")] {
+            if let Some(path) = dirs.iter().map(|dir| dir.join(name)).find(|path| path.is_file()) {
+                return Ok((tool, path));
+            }
+        }
+        Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                              "Neither update-rc.d nor chkconfig is installed".to_string()))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            LinkTool::UpdateRcD => "update-rc.d",
+            LinkTool::Chkconfig => "chkconfig",
+        }
+    }
+
+    // Argument lists installing, enabling, disabling and removing the links
+    fn commands(self, name: &str, action: Links) -> Vec<Vec<&str>> {
+        match (self, action) {
+            (LinkTool::UpdateRcD, Links::Enable) => vec![vec![name, "defaults"],
+                                                         vec![name, "enable"]],
+            (LinkTool::UpdateRcD, Links::Disable) => vec![vec![name, "disable"]],
+            (LinkTool::UpdateRcD, Links::Remove) => vec![vec!["-f", name, "remove"]],
+            (LinkTool::Chkconfig, Links::Enable) => vec![vec!["--add", name], vec![name, "on"]],
+            (LinkTool::Chkconfig, Links::Disable) => vec![vec![name, "off"]],
+            (LinkTool::Chkconfig, Links::Remove) => vec![vec!["--del", name]],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Links {
+    Enable,
+    Disable,
+    Remove,
+}
+
+/// Service of SysV init, run by an LSB `/etc/init.d/<name>` script through start-stop-daemon
+pub struct SombraSysV {
+    spec: ServiceSpec,
+    init_dir: PathBuf,
+}
+
+impl SombraSysV {
+    fn script_content(marker: &Marker) -> crate::Result<String> {
+        let spec = &marker.spec;
+        let description = match &spec.description {
+            Some(description) => description.clone(),
+            None => format!("{} service", spec.name),
+        };
+        // LSB headers are single comment lines
+        let description: String = description.chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+
+        let log_files = SombraSysV::log_files(spec);
+        let mut lines = vec![
+            format!("DAEMON={}", quote_path(&spec.executable)?),
+            format!("PIDFILE={}", quote_path(&pid_path(&spec.name))?),
+            format!("OUTPUT={}", quote_path(&log_files.stdout)?),
+            format!("ERRORS={}",
+                    quote_path(log_files.stderr.as_ref().unwrap_or(&log_files.stdout))?),
+        ];
+        for (key, value) in spec.environment()? {
+            lines.push(shell::export(&key, &value)?);
+        }
+
+        let user = match &spec.user {
+            Some(user) => user.clone(),
+            None => whoami::username(),
+        };
+        let account = match &spec.group {
+            Some(group) => format!("{}:{}", user, group),
+            None => user,
+        };
+        let mut options = vec![format!("--chuid {}", shell::quote(&account))];
+        if let Some(WorkingDirectory::Path(path)) = &spec.working_directory {
+            options.push(format!("--chdir {}", quote_path(path)?));
+        }
+        // start-stop-daemon closes the output of background processes, a shell redirects it
+        let mut words = vec!["\"$OUTPUT\"".to_string(), "\"$ERRORS\"".to_string(),
+                             "\"$DAEMON\"".to_string()];
+        words.extend(spec.args.iter().map(|arg| shell::quote(arg)));
+
+        Ok(format!("#!/bin/sh\n\
+                    ### BEGIN INIT INFO\n\
+                    # Provides:          {name}\n\
+                    # Required-Start:    $remote_fs $network\n\
+                    # Required-Stop:     $remote_fs $network\n\
+                    # Default-Start:     2 3 4 5\n\
+                    # Default-Stop:      0 1 6\n\
+                    # Short-Description: {description}\n\
+                    ### END INIT INFO\n\
+                    \n\
+                    {lines}\n\
+                    \n\
+                    do_start() {{\n\
+                    \tstart-stop-daemon --start --quiet --oknodo --background \\\n\
+                    \t\t--make-pidfile --pidfile \"$PIDFILE\" --exec \"$DAEMON\" {options} \\\n\
+                    \t\t--startas /bin/sh -- -c 'exec 1>>\"$1\" 2>>\"$2\"; shift 2; exec \"$@\"' \\\n\
+                    \t\tsh {words}\n\
+                    }}\n\
+                    \n\
+                    do_stop() {{\n\
+                    \tstart-stop-daemon --stop --quiet --oknodo --retry=TERM/10/KILL/5 \
+                    --pidfile \"$PIDFILE\"\n\
+                    \tstatus=$?\n\
+                    \t[ $status -ne 0 ] || rm -f \"$PIDFILE\"\n\
+                    \treturn $status\n\
+                    }}\n\
+                    \n\
+                    case \"$1\" in\n\
+                    \tstart) do_start ;;\n\
+                    \tstop) do_stop ;;\n\
+                    \trestart|force-reload) do_stop && do_start ;;\n\
+                    \t# 0 running, 1 dead with a pidfile left, 3 stopped\n\
+                    \tstatus) start-stop-daemon --status --pidfile \"$PIDFILE\" ;;\n\
+                    \t*) echo \"Usage: $0 {{start|stop|restart|force-reload|status}}\" >&2; \
+                    exit 3 ;;\n\
+                    esac\n\
+                    \n\
+                    {marker}",
+                   name = spec.name,
+                   description = description,
+                   lines = lines.join("\n"),
+                   options = options.join(" "),
+                   words = words.join(" "),
+                   marker = marker::render_comment(marker)))
+    }
+
+    // SysV init has no journal, output goes to `/var/log/<name>.log` unless the spec says otherwise
+    fn log_files(spec: &ServiceSpec) -> LogFiles {
+        match &spec.log_files {
+            Some(log_files) => log_files.clone(),
+            None => LogFiles::new(&format!("{}/{}.log", LOG_DIR, spec.name)),
+        }
+    }
+
+    // LSB exit codes of the status action
+    fn parse_status(code: Option<i32>) -> ServiceState {
+        match code {
+            Some(0) => ServiceState::Running,
+            Some(1) => ServiceState::Failed,
+            _ => ServiceState::Stopped,
+        }
+    }
+
+    fn script_path(&self) -> PathBuf {
+        self.init_dir.join(&self.spec.name)
+    }
+
+    fn script_name(&self) -> String {
+        format!("{}/{}", INIT_DIR, self.spec.name)
+    }
+
+    fn run_script(&self, action: &str) -> crate::Result<tool::Output> {
+        tool::run(&self.script_path(), &self.script_name(), &[action])
+    }
+
+    fn links(&self, action: Links) -> crate::Result<()> {
+        let (link_tool, path) = LinkTool::find()?;
+        for args in link_tool.commands(&self.spec.name, action) {
+            tool::run(&path, link_tool.name(), &args)?.check()?;
+        }
+        Ok(())
+    }
+
+    // Spec the service was created from, as recorded in its script
+    fn stored_spec(&self) -> crate::Result<Option<ServiceSpec>> {
+        let script = std::fs::read_to_string(self.script_path())?;
+        Ok(marker::parse_comment(&self.spec.name, &script).map(|marker| marker.spec))
+    }
+
+    // Starts the service, then waits for the readiness probe reading the output files
+    fn launch(&self, readiness: Option<&Readiness>, log_files: &LogFiles) -> crate::Result<()> {
+        let sizes = log_files.prepare()?;
+        self.run_script("start")?.check()?;
+        match readiness {
+            Some(readiness) => readiness.wait(&self.spec.name, || log_files.output_since(&sizes)),
+            None => Ok(()),
+        }
+    }
+
+    fn remove(&self, force: bool) -> crate::Result<()> {
+        self.check_privileges("Deleting")?;
+        if !force && self.stored_spec()?.is_none() {
+            return Err(crate::Error::new(crate::ErrorKind::NotManaged,
+                                         format!("Service {} was not created by sombra",
+                                                 self.spec.name)));
+        }
+        let _ = self.run_script("stop");
+        // Fails when the script has no links
+        let _ = self.links(Links::Remove);
+        Ok(std::fs::remove_file(self.script_path())?)
+    }
+
+    fn check_exists(&self) -> crate::Result<()> {
+        if self.script_path().exists() {
+            Ok(())
+        } else {
+            Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} does not exist",
+                                                                self.spec.name)))
+        }
+    }
+
+    fn check_privileges(&self, operation: &str) -> crate::Result<()> {
+        if account::is_privileged(&self.init_dir) {
+            return Ok(());
+        }
+        Err(crate::Error::new(crate::ErrorKind::PermissionDenied,
+                              format!("{} service {} needs root privileges",
+                                      operation, self.spec.name))
+            .content(self.init_dir.display().to_string()))
+    }
+}
+
+fn pid_path(name: &str) -> PathBuf {
+    Path::new(PID_DIR).join(format!("{}.pid", name))
+}
+
+fn quote_path(path: &Path) -> crate::Result<String> {
+    match path.to_str() {
+        Some(path) => Ok(shell::quote(path)),
+        None => Err(crate::Error::new(crate::ErrorKind::Io, "Cannot decode path".to_string())
+            .content(path.display().to_string())),
+    }
+}
+
+fn unsupported(what: &str) -> crate::Error {
+    crate::Error::new(crate::ErrorKind::Unsupported, format!("{} not supported by SysV init", what))
+}
+
+impl Sombra for SombraSysV {
+    fn from_spec(spec: ServiceSpec) -> crate::Result<Self> {
+        let spec = spec.canonicalize()?;
+        if spec.scope == Scope::User {
+            return Err(unsupported("User services are"));
+        }
+        if spec.root.is_some() {
+            return Err(unsupported("Installing into a root directory is"));
+        }
+        if spec.linger {
+            return Err(crate::Error::new(crate::ErrorKind::Other,
+                                         "Lingering only applies to user services".to_string()));
+        }
+        if !spec.supplementary_groups.is_empty() || !spec.ambient_capabilities.is_empty() {
+            return Err(unsupported("Supplementary groups and capabilities are"));
+        }
+        // Nothing watches the process once start-stop-daemon has forked it
+        if spec.restart.mode != RestartMode::Never {
+            return Err(unsupported("Restarting exited services is"));
+        }
+        if let Some(user) = &spec.user {
+            account::user(user)?;
+        }
+        if let Some(group) = &spec.group {
+            account::group(group)?;
+        }
+
+        Ok(SombraSysV {
+            spec,
+            init_dir: PathBuf::from(INIT_DIR),
+        })
+    }
+
+    fn list_in(scope: Scope) -> crate::Result<Vec<Self>> {
+        if scope == Scope::User {
+            return Err(unsupported("User services are"));
+        }
+        let mut services = vec![];
+        for entry in std::fs::read_dir(INIT_DIR)? {
+            let path = entry?.path();
+            let name = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            let marker = match std::fs::read_to_string(&path).ok()
+                .and_then(|script| marker::parse_comment(&name, &script)) {
+                Some(marker) => marker,
+                None => continue,
+            };
+            services.push(SombraSysV {
+                spec: marker.spec,
+                init_dir: PathBuf::from(INIT_DIR),
+            });
+        }
+        services.sort_by(|a, b| a.spec.name.cmp(&b.spec.name));
+        Ok(services)
+    }
+
+    fn spec(&self) -> &ServiceSpec {
+        &self.spec
+    }
+
+    fn create(&self) -> crate::Result<()> {
+        self.check_privileges("Creating")?;
+
+        let path = self.script_path();
+        if path.exists() {
+            return Err(crate::Error::new(crate::ErrorKind::Io, format!("Service {} already exist",
+                                         self.spec.name)));
+        }
+        let script = SombraSysV::script_content(&Marker::new(&self.spec))?;
+        std::fs::create_dir_all(&self.init_dir)?;
+        std::fs::write(&path, script)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+
+        match self.spec.startup {
+            StartupMode::Disabled => return Ok(()),
+            StartupMode::Boot => self.links(Links::Enable)?,
+            StartupMode::Manual => (),
+        }
+        self.launch(self.spec.readiness.as_ref(), &SombraSysV::log_files(&self.spec))?;
+        if self.spec.readiness.is_some() {
+            return Ok(());
+        }
+
+        // Without a probe, give the process a moment to fail
+        std::thread::sleep(Duration::from_millis(100));
+        let status = self.status()?;
+        if !status.is_running() {
+            return Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                         format!("Service {} is {} after start, expected running",
+                                                 self.spec.name, status.state))
+                .content(format!("{} status", self.script_name())));
+        }
+        Ok(())
+    }
+
+    fn delete(&self) -> crate::Result<()> {
+        self.remove(false)
+    }
+
+    fn force_delete(&self) -> crate::Result<()> {
+        self.remove(true)
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        self.check_privileges("Starting")?;
+        self.check_exists()?;
+        let stored = self.stored_spec()?.unwrap_or_else(|| self.spec.clone());
+        let readiness = match &self.spec.readiness {
+            Some(readiness) => Some(readiness.clone()),
+            None => stored.readiness.clone(),
+        };
+        self.launch(readiness.as_ref(), &SombraSysV::log_files(&stored))
+    }
+
+    fn stop(&self) -> crate::Result<()> {
+        self.check_privileges("Stopping")?;
+        self.check_exists()?;
+        self.run_script("stop")?.check().map(|_| ())
+    }
+
+    fn enable(&self) -> crate::Result<()> {
+        self.check_privileges("Enabling")?;
+        self.check_exists()?;
+        self.links(Links::Enable)
+    }
+
+    fn disable(&self) -> crate::Result<()> {
+        self.check_privileges("Disabling")?;
+        self.check_exists()?;
+        self.links(Links::Disable)
+    }
+
+    fn status(&self) -> crate::Result<ServiceStatus> {
+        if !self.script_path().exists() {
+            return Ok(ServiceStatus::new(ServiceState::NotInstalled));
+        }
+        let output = self.run_script("status")?;
+        let mut status = ServiceStatus::new(SombraSysV::parse_status(output.code));
+        if status.is_running() {
+            // start-stop-daemon writes the pidfile when it starts the process
+            let pid_path = pid_path(&self.spec.name);
+            status.main_pid = std::fs::read_to_string(&pid_path).ok()
+                .and_then(|pid| pid.trim().parse().ok());
+            status.uptime = std::fs::metadata(&pid_path).and_then(|meta| meta.modified()).ok()
+                .and_then(|started| SystemTime::now().duration_since(started).ok());
+            status.health = crate::health::load(&crate::health::health_dir(self.spec.scope),
+                                                &self.spec.name);
+        }
+        Ok(status)
+    }
+
+    fn logs(&self, query: &LogQuery) -> crate::Result<Logs> {
+        let stored = match &self.spec.log_files {
+            Some(_) => None,
+            None => self.stored_spec().ok().flatten(),
+        };
+        SombraSysV::log_files(stored.as_ref().unwrap_or(&self.spec)).logs(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RestartPolicy;
+
+    fn script(spec: ServiceSpec) -> String {
+        let spec = spec.canonicalize().unwrap();
+        SombraSysV::script_content(&Marker::new(&spec)).unwrap()
+    }
+
+    #[test]
+    fn script_has_lsb_header() {
+        let script = script(ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .description("Echo\nserver")
+            .args(vec!["-p".to_string(), "30 222".to_string()])
+            .user("root")
+            .env("GREETING", "it's $HOME"));
+        let lines: Vec<_> = script.lines().collect();
+        assert_eq!(lines[0], "#!/bin/sh");
+        assert_eq!(lines[1], "### BEGIN INIT INFO");
+        assert!(lines.contains(&"# Provides:          tcp_echo"));
+        assert!(lines.contains(&"# Short-Description: Echo server"));
+        assert!(lines.contains(&"PIDFILE='/run/tcp_echo.pid'"));
+        assert!(lines.contains(&"OUTPUT='/var/log/tcp_echo.log'"));
+        assert!(lines.contains(&"export GREETING='it'\\''s $HOME'"));
+        assert!(script.contains("--chuid 'root'"));
+        assert!(script.contains("sh \"$OUTPUT\" \"$ERRORS\" \"$DAEMON\" '-p' '30 222'\n"));
+
+        let marker = marker::parse_comment("tcp_echo", &script).unwrap();
+        assert_eq!(marker.spec.args, vec!["-p", "30 222"]);
+        assert_eq!(marker.spec.user, Some("root".to_string()));
+
+        let syntax = std::process::Command::new("sh").args(["-n", "-c", &script])
+            .status().unwrap();
+        assert!(syntax.success());
+    }
+
+    #[test]
+    fn marker_keeps_line_breaks() {
+        let script = script(ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .description("Echo\nserver")
+            .arg("x\nLogStdout=/etc/shadow"));
+        let marker = marker::parse_comment("tcp_echo", &script).unwrap();
+        assert_eq!(marker.spec.description, Some("Echo\nserver".to_string()));
+        assert_eq!(marker.spec.args, vec!["x\nLogStdout=/etc/shadow"]);
+        assert_eq!(marker.spec.log_files, None);
+    }
+
+    #[test]
+    fn restarts_are_rejected() {
+        let spec = ServiceSpec::new("tcp_echo", "executables/tcp_echo")
+            .restart(RestartPolicy::new(RestartMode::OnFailure));
+        let error = SombraSysV::from_spec(spec).err().unwrap();
+        assert_eq!(error.kind(), &crate::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn parse_status_codes() {
+        assert_eq!(SombraSysV::parse_status(Some(0)), ServiceState::Running);
+        assert_eq!(SombraSysV::parse_status(Some(1)), ServiceState::Failed);
+        assert_eq!(SombraSysV::parse_status(Some(3)), ServiceState::Stopped);
+        assert_eq!(SombraSysV::parse_status(None), ServiceState::Stopped);
+    }
+
+    #[test]
+    fn link_commands() {
+        assert_eq!(LinkTool::UpdateRcD.commands("tcp_echo", Links::Enable),
+                   vec![vec!["tcp_echo", "defaults"], vec!["tcp_echo", "enable"]]);
+        assert_eq!(LinkTool::Chkconfig.commands("tcp_echo", Links::Remove),
+                   vec![vec!["--del", "tcp_echo"]]);
+    }
+}
@@ -0,0 +1,87 @@
+// Runs the command line tools of the service managers
+
+use std::io::Read;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// Longer than the default stop timeout of the service managers, so only a hung tool hits it
+const TIMEOUT: Duration = Duration::from_secs(120);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub struct Output {
+    pub command_line: String,
+    pub code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl Output {
+    /// Fails unless the tool exited with success, returns its stdout
+    pub fn check(self) -> crate::Result<String> {
+        if self.code == Some(0) {
+            return Ok(self.stdout);
+        }
+
+        let message = match self.stderr.trim() {
+            "" => format!("{} failed", self.command_line.split(' ').next().unwrap_or_default()),
+            stderr => stderr.to_string(),
+        };
+        let error = crate::Error::new(crate::ErrorKind::ServiceManager, message)
+            .content(self.command_line);
+        Err(match self.code {
+            Some(code) => error.exit_code(code),
+            // Killed by a signal
+            None => error,
+        })
+    }
+}
+
+// Pipes are read while waiting, a full pipe would block the tool
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut buffer = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buffer);
+        }
+        String::from_utf8_lossy(&buffer).into_owned()
+    })
+}
+
+/// Runs `program`, reported as `name` in errors, killing it once TIMEOUT elapses
+pub fn run(program: &Path, name: &str, args: &[&str]) -> crate::Result<Output> {
+    let command_line = format!("{} {}", name, args.join(" "));
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| crate::Error::from(e).content(command_line.clone()))?;
+
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if started.elapsed() > TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(crate::Error::new(crate::ErrorKind::ServiceManager,
+                                         format!("Timed out after {}s", TIMEOUT.as_secs()))
+                .content(command_line));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(Output {
+        command_line,
+        code: status.code(),
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    })
+}
@@ -0,0 +1,132 @@
+use std::str::FromStr;
+use std::time::SystemTime;
+
+/// Syslog severity of a log line, from the most to the least severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Emergency,
+    Alert,
+    Critical,
+    Error,
+    Warning,
+    Notice,
+    Info,
+    Debug,
+}
+
+const PRIORITIES: [(Priority, &str); 8] = [
+    (Priority::Emergency, "emerg"),
+    (Priority::Alert, "alert"),
+    (Priority::Critical, "crit"),
+    (Priority::Error, "err"),
+    (Priority::Warning, "warning"),
+    (Priority::Notice, "notice"),
+    (Priority::Info, "info"),
+    (Priority::Debug, "debug"),
+];
+
+impl Priority {
+    /// Syslog level, 0 for emergency up to 7 for debug
+    pub fn level(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_level(level: u8) -> Option<Self> {
+        PRIORITIES.get(level as usize).map(|(priority, _)| *priority)
+    }
+}
+
+impl FromStr for Priority {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        if let Some(priority) = s.parse().ok().and_then(Priority::from_level) {
+            return Ok(priority);
+        }
+        PRIORITIES.iter()
+            .find(|(_, name)| *name == s)
+            .map(|(priority, _)| *priority)
+            .ok_or_else(|| crate::Error::new(crate::ErrorKind::Other,
+                                             format!("Invalid priority {}, expected one of \
+                                                      emerg, alert, crit, err, warning, \
+                                                      notice, info, debug or 0-7", s)))
+    }
+}
+
+impl std::fmt::Display for Priority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", PRIORITIES[*self as usize].1)
+    }
+}
+
+/// One line written by a service
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogLine {
+    /// Unknown for lines read from output files
+    pub timestamp: Option<SystemTime>,
+    /// Unknown when the backend does not record severities
+    pub priority: Option<Priority>,
+    pub message: String,
+}
+
+/// Selects the lines returned by `Sombra::logs`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LogQuery {
+    pub(crate) lines: Option<usize>,
+    pub(crate) since: Option<SystemTime>,
+    pub(crate) until: Option<SystemTime>,
+    pub(crate) priority: Option<Priority>,
+    pub(crate) follow: bool,
+}
+
+impl LogQuery {
+    pub fn new() -> Self {
+        LogQuery::default()
+    }
+
+    /// Keeps only the most recent lines
+    pub fn lines(mut self, lines: usize) -> Self {
+        self.lines = Some(lines);
+        self
+    }
+
+    pub fn since(mut self, since: SystemTime) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: SystemTime) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Keeps only lines of this priority or more severe
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Keeps waiting for new lines once the existing ones are returned
+    pub fn follow(mut self) -> Self {
+        self.follow = true;
+        self
+    }
+}
+
+/// Lines returned by `Sombra::logs`, when following they are read as the service writes them
+pub struct Logs(Box<dyn Iterator<Item = crate::Result<LogLine>>>);
+
+impl Logs {
+    pub(crate) fn new<I>(lines: I) -> Self
+        where I: Iterator<Item = crate::Result<LogLine>> + 'static {
+        Logs(Box::new(lines))
+    }
+}
+
+impl Iterator for Logs {
+    type Item = crate::Result<LogLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
+    }
+}
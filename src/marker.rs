@@ -0,0 +1,266 @@
+use crate::{FileMode, HealthAction, Liveness, LogFiles, Probe, Readiness, ServiceSpec};
+use crate::spec::WorkingDirectory;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Ownership record stored with every service created by sombra, it keeps
+/// the spec the service was created from so it can be rebuilt later
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub version: String,
+    /// Creation time, in seconds since the Unix epoch
+    pub created: u64,
+    pub spec: ServiceSpec,
+}
+
+impl Marker {
+    pub fn new(spec: &ServiceSpec) -> Self {
+        let created = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Marker {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            created,
+            spec: spec.clone(),
+        }
+    }
+
+    /// Flattens the marker to ordered key/value pairs, keys may repeat and values hold no
+    /// line breaks
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let spec = &self.spec;
+        let mut entries = vec![
+            ("Version", self.version.clone()),
+            ("Created", self.created.to_string()),
+            ("Executable", escape(&spec.executable.display().to_string())),
+        ];
+        for arg in &spec.args {
+            entries.push(("Argument", escape(arg)));
+        }
+        if let Some(description) = &spec.description {
+            entries.push(("Description", escape(description)));
+        }
+        entries.push(("Startup", spec.startup.to_string()));
+        for (key, value) in &spec.env {
+            entries.push(("Environment", format!("{}={}", key, escape(value))));
+        }
+        if let Some(env_file) = &spec.env_file {
+            entries.push(("EnvironmentFile", escape(&env_file.display().to_string())));
+        }
+        if let Some(WorkingDirectory::Path(path)) = &spec.working_directory {
+            entries.push(("WorkingDirectory", escape(&path.display().to_string())));
+        }
+        if let Some(user) = &spec.user {
+            entries.push(("User", escape(user)));
+        }
+        if let Some(group) = &spec.group {
+            entries.push(("Group", escape(group)));
+        }
+        for group in &spec.supplementary_groups {
+            entries.push(("SupplementaryGroup", escape(group)));
+        }
+        for capability in &spec.ambient_capabilities {
+            entries.push(("AmbientCapability", escape(capability)));
+        }
+
+        let restart = &spec.restart;
+        entries.push(("Restart", restart.mode.to_string()));
+        if let Some(delay) = restart.delay {
+            entries.push(("RestartDelayMs", delay.as_millis().to_string()));
+        }
+        if let Some((count, interval)) = restart.burst {
+            entries.push(("RestartBurst", format!("{}/{}", count, interval.as_millis())));
+        }
+        if let Some((max_delay, steps)) = restart.backoff {
+            entries.push(("RestartBackoff", format!("{}/{}", max_delay.as_millis(), steps)));
+        }
+        for code in &restart.success_exit_codes {
+            entries.push(("SuccessExitCode", code.to_string()));
+        }
+        for code in &restart.prevent_exit_codes {
+            entries.push(("PreventExitCode", code.to_string()));
+        }
+
+        if let Some(readiness) = &spec.readiness {
+            entries.push(("Readiness", probe_entry(&readiness.probe)));
+            if let Probe::Command(_, args) = &readiness.probe {
+                for arg in args {
+                    entries.push(("ReadinessArgument", escape(arg)));
+                }
+            }
+            entries.push(("ReadinessTimeoutMs", readiness.timeout.as_millis().to_string()));
+            entries.push(("ReadinessIntervalMs", readiness.interval.as_millis().to_string()));
+        }
+        if let Some(liveness) = &spec.liveness {
+            entries.push(("Liveness", probe_entry(&liveness.probe)));
+            if let Probe::Command(_, args) = &liveness.probe {
+                for arg in args {
+                    entries.push(("LivenessArgument", escape(arg)));
+                }
+            }
+            entries.push(("LivenessIntervalMs", liveness.interval.as_millis().to_string()));
+            entries.push(("LivenessThreshold", liveness.threshold.to_string()));
+            entries.push(("LivenessAction", escape(&liveness.action.to_string())));
+            if let HealthAction::Hook(_, args) = &liveness.action {
+                for arg in args {
+                    entries.push(("LivenessHookArgument", escape(arg)));
+                }
+            }
+        }
+
+        if let Some(log_files) = &spec.log_files {
+            entries.push(("LogStdout", escape(&log_files.stdout.display().to_string())));
+            if let Some(stderr) = &log_files.stderr {
+                entries.push(("LogStderr", escape(&stderr.display().to_string())));
+            }
+            if log_files.mode == FileMode::Truncate {
+                entries.push(("LogTruncate", "true".to_string()));
+            }
+            if let Some(max_size) = log_files.max_size {
+                entries.push(("LogMaxSize", max_size.to_string()));
+            }
+            entries.push(("LogRetain", log_files.retain.to_string()));
+        }
+        entries
+    }
+
+    pub fn from_entries<'a, I>(name: &str, entries: I) -> Option<Self>
+        where I: IntoIterator<Item = (&'a str, &'a str)> {
+        let mut version = None;
+        let mut created = None;
+        let mut spec = ServiceSpec::new(name, "");
+
+        for (key, value) in entries {
+            match key {
+                "Version" => version = Some(value.to_string()),
+                "Created" => created = value.parse().ok(),
+                "Executable" => spec.executable = PathBuf::from(unescape(value)),
+                "Argument" => spec.args.push(unescape(value)),
+                "Description" => spec.description = Some(unescape(value)),
+                "Startup" => spec.startup = value.parse().ok()?,
+                "Environment" => {
+                    let mut pair = value.splitn(2, '=');
+                    spec.env.push((pair.next()?.to_string(), unescape(pair.next()?)));
+                },
+                "EnvironmentFile" => spec.env_file = Some(PathBuf::from(unescape(value))),
+                "WorkingDirectory" => spec.working_directory =
+                    Some(WorkingDirectory::Path(PathBuf::from(unescape(value)))),
+                "User" => spec.user = Some(unescape(value)),
+                "Group" => spec.group = Some(unescape(value)),
+                "SupplementaryGroup" => spec.supplementary_groups.push(unescape(value)),
+                "AmbientCapability" => spec.ambient_capabilities.push(unescape(value)),
+                "Restart" => spec.restart.mode = value.parse().ok()?,
+                "RestartDelayMs" => spec.restart.delay = Some(millis(value)?),
+                "RestartBurst" => {
+                    let (count, interval) = split_pair(value)?;
+                    spec.restart.burst = Some((count.parse().ok()?, millis(interval)?));
+                },
+                "RestartBackoff" => {
+                    let (max_delay, steps) = split_pair(value)?;
+                    spec.restart.backoff = Some((millis(max_delay)?, steps.parse().ok()?));
+                },
+                "SuccessExitCode" => spec.restart.success_exit_codes.push(value.parse().ok()?),
+                "PreventExitCode" => spec.restart.prevent_exit_codes.push(value.parse().ok()?),
+                "Readiness" => spec.readiness = Some(Readiness::new(probe(&unescape(value))?)),
+                "ReadinessArgument" => match &mut spec.readiness.as_mut()?.probe {
+                    Probe::Command(_, args) => args.push(unescape(value)),
+                    _ => return None,
+                },
+                "ReadinessTimeoutMs" => spec.readiness.as_mut()?.timeout = millis(value)?,
+                "ReadinessIntervalMs" => spec.readiness.as_mut()?.interval = millis(value)?,
+                "Liveness" => spec.liveness = Some(Liveness::new(probe(&unescape(value))?)),
+                "LivenessArgument" => match &mut spec.liveness.as_mut()?.probe {
+                    Probe::Command(_, args) => args.push(unescape(value)),
+                    _ => return None,
+                },
+                "LivenessIntervalMs" => spec.liveness.as_mut()?.interval = millis(value)?,
+                "LivenessThreshold" => spec.liveness.as_mut()?.threshold = value.parse().ok()?,
+                "LivenessAction" => spec.liveness.as_mut()?.action = action(&unescape(value))?,
+                "LogStdout" => spec.log_files = Some(LogFiles::new(&unescape(value))),
+                "LogStderr" => spec.log_files.as_mut()?.stderr =
+                    Some(PathBuf::from(unescape(value))),
+                "LogTruncate" => spec.log_files.as_mut()?.mode = FileMode::Truncate,
+                "LogMaxSize" => spec.log_files.as_mut()?.max_size = Some(value.parse().ok()?),
+                "LogRetain" => spec.log_files.as_mut()?.retain = value.parse().ok()?,
+                "LivenessHookArgument" => match &mut spec.liveness.as_mut()?.action {
+                    HealthAction::Hook(_, args) => args.push(unescape(value)),
+                    _ => return None,
+                },
+                _ => (),
+            }
+        }
+
+        Some(Marker {
+            version: version?,
+            created: created?,
+            spec,
+        })
+    }
+}
+
+fn millis(value: &str) -> Option<Duration> {
+    value.parse().ok().map(Duration::from_millis)
+}
+
+fn probe_entry(probe: &Probe) -> String {
+    let entry = match probe {
+        Probe::Tcp(address) => format!("tcp {}", address),
+        Probe::Http(url) => format!("http {}", url),
+        Probe::File(path) => format!("file {}", path.display()),
+        Probe::Command(program, _) => format!("command {}", program.display()),
+        Probe::Log(regex) => format!("log {}", regex),
+    };
+    escape(&entry)
+}
+
+fn action(value: &str) -> Option<HealthAction> {
+    match value {
+        "restart" => Some(HealthAction::Restart),
+        "stop" => Some(HealthAction::Stop),
+        _ => value.strip_prefix("hook ")
+            .map(|program| HealthAction::Hook(PathBuf::from(program), vec![])),
+    }
+}
+
+fn probe(value: &str) -> Option<Probe> {
+    let mut pair = value.splitn(2, ' ');
+    let (kind, target) = (pair.next()?, pair.next()?);
+    match kind {
+        "tcp" => Some(Probe::Tcp(target.to_string())),
+        "http" => Some(Probe::Http(target.to_string())),
+        "file" => Some(Probe::File(PathBuf::from(target))),
+        "command" => Some(Probe::Command(PathBuf::from(target), vec![])),
+        "log" => Some(Probe::Log(target.to_string())),
+        _ => None,
+    }
+}
+
+fn split_pair(value: &str) -> Option<(&str, &str)> {
+    let mut pair = value.splitn(2, '/');
+    Some((pair.next()?, pair.next()?))
+}
+
+// Marker entries are stored one per line, so line breaks inside values are escaped
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+// "<name>.service" has to fit in the 255 bytes systemd allows for a unit name
+const SYSTEMD_MAX_LEN: usize = 255 - ".service".len();
+const WINDOWS_MAX_LEN: usize = 256;
+
+/// Name of a service, checked against the naming rules of the platform backend
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServiceName(String);
+
+impl ServiceName {
+    pub fn new(name: &str) -> crate::Result<Self> {
+        #[cfg(target_os = "windows")]
+        check_windows(name)?;
+        #[cfg(not(target_os = "windows"))]
+        check_systemd(name)?;
+        Ok(ServiceName(name.to_string()))
+    }
+
+    /// Encodes an arbitrary display name into a valid service name, the way
+    /// `systemd-escape` does: "/" becomes "-" and other characters become `\xNN`
+    #[cfg(not(target_os = "windows"))]
+    pub fn escape(display_name: &str) -> crate::Result<Self> {
+        ServiceName::new(&systemd_escape(display_name))
+    }
+
+    /// Encodes an arbitrary display name into a valid service name, slashes become "-"
+    #[cfg(target_os = "windows")]
+    pub fn escape(display_name: &str) -> crate::Result<Self> {
+        ServiceName::new(&display_name.replace(['/', '\\'], "-"))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ServiceName {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        ServiceName::new(s)
+    }
+}
+
+impl std::fmt::Display for ServiceName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl AsRef<str> for ServiceName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn invalid(name: &str, desc: &str) -> crate::Error {
+    crate::Error::new(crate::ErrorKind::InvalidName, desc.to_string())
+        .content(format!("{:?}", name))
+}
+
+// Unit name prefix grammar of systemd.unit(5), without the "@" of template units
+#[cfg_attr(target_os = "windows", allow(dead_code))]
+fn check_systemd(name: &str) -> crate::Result<()> {
+    if name.is_empty() {
+        return Err(invalid(name, "Service name is empty"));
+    }
+    if name.len() > SYSTEMD_MAX_LEN {
+        return Err(invalid(name, &format!("Service name is longer than {} characters",
+                                          SYSTEMD_MAX_LEN)));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || ":-_.\\".contains(c)) {
+        return Err(invalid(name, "Service name may only contain ASCII letters, digits and \
+                                  \":-_.\\\", use ServiceName::escape for other names"));
+    }
+    // "." and ".." name the directory holding the service files, or its parent
+    if name.chars().all(|c| c == '.') {
+        return Err(invalid(name, "Service name may not consist of dots only"));
+    }
+    Ok(())
+}
+
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn check_windows(name: &str) -> crate::Result<()> {
+    if name.is_empty() {
+        return Err(invalid(name, "Service name is empty"));
+    }
+    if name.chars().count() > WINDOWS_MAX_LEN {
+        return Err(invalid(name, &format!("Service name is longer than {} characters",
+                                          WINDOWS_MAX_LEN)));
+    }
+    if name.chars().any(|c| c == '/' || c == '\\' || c.is_control()) {
+        return Err(invalid(name, "Service name may not contain slashes or control characters"));
+    }
+    Ok(())
+}
+
+#[cfg_attr(target_os = "windows", allow(dead_code))]
+fn systemd_escape(display_name: &str) -> String {
+    let mut escaped = String::new();
+    for (i, c) in display_name.chars().enumerate() {
+        match c {
+            '/' => escaped.push('-'),
+            '.' if i == 0 => escaped.push_str("\\x2e"),
+            c if c.is_ascii_alphanumeric() || ":_.".contains(c) => escaped.push(c),
+            c => {
+                let mut bytes = [0; 4];
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    escaped.push_str(&format!("\\x{:02x}", byte));
+                }
+            },
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn systemd_names() {
+        assert_eq!(check_systemd("tcp_echo-2.0:a\\x20b"), Ok(()));
+        for name in &["", ".", "..", "...", "../../tmp/x", "tcp echo", "tcp@echo", "tcp_echo\n",
+                      "ação"] {
+            let err = check_systemd(name).expect_err(name);
+            assert_eq!(err.kind(), &crate::ErrorKind::InvalidName);
+        }
+        assert!(check_systemd(&"a".repeat(SYSTEMD_MAX_LEN)).is_ok());
+        assert!(check_systemd(&"a".repeat(SYSTEMD_MAX_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn windows_names() {
+        assert_eq!(check_windows("TCP Echo (2.0)"), Ok(()));
+        for name in &["", "..\\..\\x", "tcp/echo", "tcp_echo\n"] {
+            assert!(check_windows(name).is_err(), "{:?}", name);
+        }
+        assert!(check_windows(&"ã".repeat(WINDOWS_MAX_LEN)).is_ok());
+        assert!(check_windows(&"a".repeat(WINDOWS_MAX_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn escape_like_systemd_escape() {
+        assert_eq!(systemd_escape("TCP Echo/café-2.0"), "TCP\\x20Echo-caf\\xc3\\xa9\\x2d2.0");
+        assert_eq!(systemd_escape(".hidden"), "\\x2ehidden");
+        assert!(check_systemd(&systemd_escape("../../tmp/x")).is_ok());
+    }
+}
@@ -0,0 +1,309 @@
+use crate::{LogLine, LogQuery, Logs};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_RETAIN: u32 = 5;
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How output files are opened when the service starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileMode {
+    Append,
+    Truncate,
+}
+
+/// Files receiving the stdout and stderr of a service instead of the system log
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFiles {
+    pub(crate) stdout: PathBuf,
+    /// Separate file for stderr, when `None` it goes to the stdout file
+    pub(crate) stderr: Option<PathBuf>,
+    pub(crate) mode: FileMode,
+    pub(crate) max_size: Option<u64>,
+    pub(crate) retain: u32,
+}
+
+impl LogFiles {
+    /// Writes stdout and stderr combined to one file
+    pub fn new(path: &str) -> Self {
+        LogFiles {
+            stdout: PathBuf::from(path),
+            stderr: None,
+            mode: FileMode::Append,
+            max_size: None,
+            retain: DEFAULT_RETAIN,
+        }
+    }
+
+    pub fn stderr(mut self, path: &str) -> Self {
+        self.stderr = Some(PathBuf::from(path));
+        self
+    }
+
+    /// Empties the files every time the service starts
+    pub fn truncate(mut self) -> Self {
+        self.mode = FileMode::Truncate;
+        self
+    }
+
+    /// Rotates a file once it grows past `max_size` bytes, keeping `retain` old files
+    /// as `<file>.1` (the newest) up to `<file>.<retain>`. Under systemd, OpenRC and SysV
+    /// the size is only checked when the service starts and while `sombra monitor` runs
+    pub fn rotate(mut self, max_size: u64, retain: u32) -> Self {
+        self.max_size = Some(max_size);
+        self.retain = retain;
+        self
+    }
+
+    pub(crate) fn files(&self) -> Vec<&Path> {
+        std::iter::once(self.stdout.as_path()).chain(self.stderr.as_deref()).collect()
+    }
+
+    /// Truncate mode opens the files without O_APPEND under systemd, the service would keep
+    /// writing at its old offset once a rotation empties them
+    pub(crate) fn validate(&self) -> crate::Result<()> {
+        if self.mode == FileMode::Truncate && self.max_size.is_some() {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Output files cannot be both truncated and rotated"
+                                             .to_string())
+                .content(self.stdout.display().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Files must go in an existing directory, they are created by the service manager
+    pub(crate) fn canonicalize(mut self) -> crate::Result<Self> {
+        self.stdout = file_path(&self.stdout)?;
+        self.stderr = match &self.stderr {
+            Some(stderr) => Some(file_path(stderr)?),
+            None => None,
+        };
+        Ok(self)
+    }
+
+    /// Rotates the files that grew too large, they are copied then truncated
+    /// because the service keeps writing to the open file
+    pub(crate) fn rotate_files(&self) -> crate::Result<()> {
+        let max_size = match self.max_size {
+            Some(max_size) => max_size,
+            None => return Ok(()),
+        };
+        for file in self.files() {
+            match std::fs::metadata(file) {
+                Ok(metadata) if metadata.len() > max_size => (),
+                _ => continue,
+            }
+            if self.retain == 0 {
+                OpenOptions::new().write(true).open(file)?.set_len(0)?;
+                continue;
+            }
+            let _ = std::fs::remove_file(rotated(file, self.retain));
+            for n in (1..self.retain).rev() {
+                let _ = std::fs::rename(rotated(file, n), rotated(file, n + 1));
+            }
+            std::fs::copy(file, rotated(file, 1))?;
+            OpenOptions::new().write(true).open(file)?.set_len(0)?;
+        }
+        Ok(())
+    }
+
+    /// Readies the files for init scripts, which only append: creates their directories,
+    /// empties them in truncate mode and rotates them, returning their sizes
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    pub(crate) fn prepare(&self) -> crate::Result<Vec<u64>> {
+        for file in self.files() {
+            if let Some(dir) = file.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            if self.mode == FileMode::Truncate {
+                match OpenOptions::new().write(true).open(file) {
+                    Ok(file) => file.set_len(0)?,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                    Err(e) => return Err(crate::Error::from(e)
+                        .content(file.display().to_string())),
+                }
+            }
+        }
+        self.rotate_files()?;
+        Ok(self.sizes())
+    }
+
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    pub(crate) fn sizes(&self) -> Vec<u64> {
+        self.files().iter()
+            .map(|file| std::fs::metadata(file).map(|m| m.len()).unwrap_or(0))
+            .collect()
+    }
+
+    /// Output written since `sizes` were taken, all of it for files truncated meanwhile
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    pub(crate) fn output_since(&self, sizes: &[u64]) -> crate::Result<String> {
+        let mut output = String::new();
+        for (file, &size) in self.files().iter().zip(sizes) {
+            let mut data = Vec::new();
+            let mut file = match File::open(file) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(crate::Error::from(e).content(file.display().to_string())),
+            };
+            let start = if file.metadata()?.len() < size { 0 } else { size };
+            file.seek(SeekFrom::Start(start))?;
+            file.read_to_end(&mut data)?;
+            output.push_str(&String::from_utf8_lossy(&data));
+        }
+        Ok(output)
+    }
+
+    /// Lines of the files, oldest rotated file first, stdout before stderr
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    pub(crate) fn logs(&self, query: &LogQuery) -> crate::Result<Logs> {
+        if query.since.is_some() || query.until.is_some() || query.priority.is_some() {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Output files have no timestamps or priorities"
+                                             .to_string())
+                .content(self.stdout.display().to_string()));
+        }
+
+        let mut lines = vec![];
+        for file in self.files() {
+            for n in (1..=self.retain).rev() {
+                read_lines(&rotated(file, n), &mut lines)?;
+            }
+            read_lines(file, &mut lines)?;
+        }
+        if let Some(count) = query.lines {
+            lines.drain(..lines.len().saturating_sub(count));
+        }
+        let lines = lines.into_iter().map(|message| Ok(LogLine {
+            timestamp: None,
+            priority: None,
+            message,
+        }));
+
+        if !query.follow {
+            return Ok(Logs::new(lines));
+        }
+        let follower = self.files().iter().zip(self.sizes())
+            .map(|(file, size)| (file.to_path_buf(), size, String::new()))
+            .collect();
+        Ok(Logs::new(lines.chain(Follow { files: follower, pending: vec![] })))
+    }
+}
+
+fn rotated(file: &Path, n: u32) -> PathBuf {
+    let mut name = file.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn file_path(path: &Path) -> crate::Result<PathBuf> {
+    let invalid = || crate::Error::new(crate::ErrorKind::Io, "Not a file path".to_string())
+        .content(path.display().to_string());
+    let name = path.file_name().ok_or_else(invalid)?;
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let parent = dunce::canonicalize(parent)
+        .map_err(|e| crate::Error::new(crate::ErrorKind::Io, e.to_string())
+            .content(parent.display().to_string()))?;
+    Ok(parent.join(name))
+}
+
+fn read_lines(file: &Path, lines: &mut Vec<String>) -> crate::Result<()> {
+    let file = match File::open(file) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(crate::Error::from(e).content(file.display().to_string())),
+    };
+    for line in BufReader::new(file).split(b'\n') {
+        lines.push(String::from_utf8_lossy(&line?).into_owned());
+    }
+    Ok(())
+}
+
+// Polls the files for appended lines, starting over when a file is rotated
+struct Follow {
+    files: Vec<(PathBuf, u64, String)>,
+    pending: Vec<String>,
+}
+
+impl Iterator for Follow {
+    type Item = crate::Result<LogLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if !self.pending.is_empty() {
+                return Some(Ok(LogLine {
+                    timestamp: None,
+                    priority: None,
+                    message: self.pending.remove(0),
+                }));
+            }
+            for (path, position, partial) in &mut self.files {
+                let mut file = match File::open(&path) {
+                    Ok(file) => file,
+                    Err(_) => continue,
+                };
+                let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+                if len < *position {
+                    *position = 0;
+                }
+                let mut data = Vec::new();
+                if let Err(e) = file.seek(SeekFrom::Start(*position))
+                    .and_then(|_| file.read_to_end(&mut data)) {
+                    return Some(Err(e.into()));
+                }
+                *position += data.len() as u64;
+                partial.push_str(&String::from_utf8_lossy(&data));
+                while let Some(end) = partial.find('\n') {
+                    self.pending.push(partial[..end].to_string());
+                    partial.drain(..=end);
+                }
+            }
+            if self.pending.is_empty() {
+                std::thread::sleep(FOLLOW_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_and_read() {
+        let dir = std::env::temp_dir().join(format!("sombra-output-{}", std::process::id()));
+        let _ = std::fs::create_dir_all(&dir);
+        let stdout = dir.join("tcp_echo.log");
+        let files = LogFiles::new(&stdout.display().to_string()).rotate(10, 2);
+
+        for content in &["old 1\nold 2\n", "newer 1\nnewer 2\n", "newest 1\nnewest 2\n"] {
+            std::fs::write(&stdout, content).expect("write");
+            assert_eq!(files.rotate_files(), Ok(()));
+        }
+        std::fs::write(&stdout, "current\n").expect("write");
+
+        let lines: crate::Result<Vec<_>> = files.logs(&LogQuery::new())
+            .and_then(|logs| logs.map(|line| line.map(|line| line.message)).collect());
+        let last: crate::Result<Vec<_>> = files.logs(&LogQuery::new().lines(2))
+            .and_then(|logs| logs.map(|line| line.map(|line| line.message)).collect());
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // Only two rotated files are kept, so the oldest output is gone
+        assert_eq!(lines, Ok(vec!["newer 1".to_string(), "newer 2".to_string(),
+                                  "newest 1".to_string(), "newest 2".to_string(),
+                                  "current".to_string()]));
+        assert_eq!(last, Ok(vec!["newest 2".to_string(), "current".to_string()]));
+    }
+
+    #[test]
+    fn truncate_excludes_rotation() {
+        assert!(LogFiles::new("out.log").truncate().rotate(4096, 3).validate().is_err());
+        assert!(LogFiles::new("out.log").rotate(4096, 3).validate().is_ok());
+        assert!(LogFiles::new("out.log").truncate().validate().is_ok());
+    }
+}
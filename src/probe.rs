@@ -0,0 +1,258 @@
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Check telling whether a service is able to do its job
+#[derive(Debug, Clone, PartialEq)]
+pub enum Probe {
+    /// A TCP connection to the address is accepted
+    Tcp(String),
+    /// A GET request to the `http://` URL answers with a 2xx status
+    Http(String),
+    /// The file exists
+    File(PathBuf),
+    /// The program exits with status 0
+    Command(PathBuf, Vec<String>),
+    /// A line of the service log matches the regex
+    Log(String),
+}
+
+/// Probe `create` and `start` wait for before returning
+#[derive(Debug, Clone, PartialEq)]
+pub struct Readiness {
+    pub(crate) probe: Probe,
+    pub(crate) timeout: Duration,
+    pub(crate) interval: Duration,
+}
+
+impl Readiness {
+    pub fn new(probe: Probe) -> Self {
+        Readiness {
+            probe,
+            timeout: DEFAULT_TIMEOUT,
+            interval: DEFAULT_INTERVAL,
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Delay between two attempts of the probe
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Runs the probe until it passes, `log` reads what the service logged since it started
+    pub(crate) fn wait<F>(&self, name: &str, log: F) -> crate::Result<()>
+        where F: Fn() -> crate::Result<String> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let last = match self.probe.check(deadline, &log) {
+                Ok(()) => return Ok(()),
+                Err(last) => last,
+            };
+            if Instant::now() + self.interval > deadline {
+                return Err(crate::Error::new(crate::ErrorKind::Timeout,
+                                             format!("Service {} not ready after {}ms: {}",
+                                                     name, self.timeout.as_millis(), last))
+                    .content(self.probe.to_string()));
+            }
+            std::thread::sleep(self.interval);
+        }
+    }
+}
+
+impl Probe {
+    pub(crate) fn validate(&self) -> crate::Result<()> {
+        let invalid = |desc: String| crate::Error::new(crate::ErrorKind::Other, desc)
+            .content(self.to_string());
+        match self {
+            Probe::Http(url) => http_target(url).map(|_| ()).map_err(invalid),
+            Probe::Log(regex) => regex::Regex::new(regex).map(|_| ())
+                .map_err(|e| invalid(e.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs the probe once, describing why it failed
+    pub(crate) fn check<F>(&self, deadline: Instant, log: F) -> Result<(), String>
+        where F: Fn() -> crate::Result<String> {
+        match self {
+            Probe::Tcp(address) => connect(address).map(|_| ()),
+            Probe::Http(url) => http_get(url),
+            Probe::File(path) => if path.exists() {
+                Ok(())
+            } else {
+                Err("file does not exist".to_string())
+            },
+            Probe::Command(program, args) => run(program, args, deadline),
+            Probe::Log(regex) => {
+                let regex = regex::Regex::new(regex).map_err(|e| e.to_string())?;
+                let log = log().map_err(|e| e.to_string())?;
+                if log.lines().any(|line| regex.is_match(line)) {
+                    Ok(())
+                } else {
+                    Err("no matching log line".to_string())
+                }
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Tcp(address) => write!(f, "tcp {}", address),
+            Probe::Http(url) => write!(f, "http {}", url),
+            Probe::File(path) => write!(f, "file {}", path.display()),
+            Probe::Command(program, args) if args.is_empty() =>
+                write!(f, "command {}", program.display()),
+            Probe::Command(program, args) =>
+                write!(f, "command {} {}", program.display(), args.join(" ")),
+            Probe::Log(regex) => write!(f, "log {}", regex),
+        }
+    }
+}
+
+fn connect(address: &str) -> Result<TcpStream, String> {
+    let mut last = format!("cannot resolve {}", address);
+    for address in address.to_socket_addrs().map_err(|e| e.to_string())? {
+        match TcpStream::connect_timeout(&address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = e.to_string(),
+        }
+    }
+    Err(last)
+}
+
+// Splits an http:// URL into the address to connect to, the host header and the path
+fn http_target(url: &str) -> Result<(String, String, String), String> {
+    let rest = match url.strip_prefix("http://") {
+        Some(rest) => rest,
+        None => return Err("only http:// URLs are supported".to_string()),
+    };
+    let (host, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() {
+        return Err("URL without host".to_string());
+    }
+    let address = match host.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => host.to_string(),
+        _ => format!("{}:80", host),
+    };
+    Ok((address, host.to_string(), path.to_string()))
+}
+
+fn http_get(url: &str) -> Result<(), String> {
+    let (address, host, path) = http_target(url)?;
+    let mut stream = connect(&address)?;
+    let _ = stream.set_read_timeout(Some(CONNECT_TIMEOUT));
+    let request = format!("GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host);
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    let _ = stream.read_to_end(&mut response);
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') && code.len() == 3 => Ok(()),
+        Some(_) => Err(status_line.to_string()),
+        None => Err("no HTTP response".to_string()),
+    }
+}
+
+fn run(program: &Path, args: &[String], deadline: Instant) -> Result<(), String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    match wait(&mut child, deadline)? {
+        status if status.success() => Ok(()),
+        status => Err(status.to_string()),
+    }
+}
+
+/// Waits for the child to exit, killing it once the deadline has passed
+pub(crate) fn wait(child: &mut Child, deadline: Instant) -> Result<ExitStatus, String> {
+    loop {
+        match child.try_wait().map_err(|e| e.to_string())? {
+            Some(status) => return Ok(status),
+            None if Instant::now() > deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err("command did not exit".to_string());
+            },
+            None => std::thread::sleep(Duration::from_millis(10)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn no_log() -> crate::Result<String> {
+        Ok(String::new())
+    }
+
+    #[test]
+    fn tcp_and_http_probes() {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let address = listener.local_addr().expect("address").to_string();
+        let server = std::thread::spawn(move || {
+            for status in &["204 No Content", "503 Service Unavailable"] {
+                let (mut stream, _) = listener.accept().expect("accept");
+                let mut request = [0u8; 512];
+                let _ = stream.read(&mut request);
+                let _ = stream.write_all(format!("HTTP/1.0 {}\r\n\r\n", status).as_bytes());
+            }
+        });
+
+        let deadline = Instant::now() + DEFAULT_TIMEOUT;
+        let url = format!("http://{}/health", address);
+        assert_eq!(Probe::Http(url.clone()).check(deadline, no_log), Ok(()));
+        assert_eq!(Probe::Http(url).check(deadline, no_log),
+                   Err("HTTP/1.0 503 Service Unavailable".to_string()));
+        let _ = server.join();
+        assert!(Probe::Http("https://localhost".to_string()).validate().is_err());
+        assert!(Probe::Tcp(address).check(deadline, no_log).is_err());
+    }
+
+    #[test]
+    fn log_probe_matches_lines() {
+        let probe = Probe::Log("^Listening at .*:30222$".to_string());
+        let deadline = Instant::now() + DEFAULT_TIMEOUT;
+        assert_eq!(probe.check(deadline, || Ok("Starting\nListening at 127.0.0.1:30222\n"
+            .to_string())), Ok(()));
+        assert!(probe.check(deadline, || Ok("Starting\n".to_string())).is_err());
+        assert!(Probe::Log("(".to_string()).validate().is_err());
+    }
+
+    #[test]
+    fn timeout_reports_last_result() {
+        let readiness = Readiness::new(Probe::File(PathBuf::from("no/such/file")))
+            .timeout(Duration::from_millis(50))
+            .interval(Duration::from_millis(10));
+        let err = readiness.wait("tcp_echo", no_log).expect_err("file probe passed");
+        assert_eq!(err.kind(), &crate::ErrorKind::Timeout);
+        assert!(err.to_string().contains("file does not exist"));
+
+        let readiness = Readiness::new(Probe::File(PathBuf::from("Cargo.toml")));
+        assert_eq!(readiness.wait("tcp_echo", no_log), Ok(()));
+    }
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+// Same as RestartSec of systemd
+const DEFAULT_DELAY: Duration = Duration::from_millis(100);
+
+/// When a service process is restarted after it exits
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RestartMode {
+    #[default]
+    Never,
+    /// On a non-zero exit code, a signal, a timeout or a watchdog
+    OnFailure,
+    /// On any exit, clean or not
+    Always,
+    /// On a signal, a timeout or a watchdog, but not on an exit code
+    OnAbnormal,
+}
+
+impl std::str::FromStr for RestartMode {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s {
+            "never" => Ok(RestartMode::Never),
+            "on-failure" => Ok(RestartMode::OnFailure),
+            "always" => Ok(RestartMode::Always),
+            "on-abnormal" => Ok(RestartMode::OnAbnormal),
+            _ => Err(crate::Error::new(crate::ErrorKind::Other,
+                                       format!("Unknown restart mode {}, expected never, \
+                                                on-failure, always or on-abnormal", s))),
+        }
+    }
+}
+
+impl std::fmt::Display for RestartMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = match self {
+            RestartMode::Never => "never",
+            RestartMode::OnFailure => "on-failure",
+            RestartMode::Always => "always",
+            RestartMode::OnAbnormal => "on-abnormal",
+        };
+        write!(f, "{}", mode)
+    }
+}
+
+/// Restart policy of a service, see `ServiceSpec::restart`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RestartPolicy {
+    pub(crate) mode: RestartMode,
+    pub(crate) delay: Option<Duration>,
+    pub(crate) burst: Option<(u32, Duration)>,
+    pub(crate) backoff: Option<(Duration, u32)>,
+    pub(crate) success_exit_codes: Vec<i32>,
+    pub(crate) prevent_exit_codes: Vec<i32>,
+}
+
+impl RestartPolicy {
+    pub fn new(mode: RestartMode) -> Self {
+        RestartPolicy {
+            mode,
+            ..RestartPolicy::default()
+        }
+    }
+
+    /// Time to wait before restarting
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Gives up after `count` starts within `interval`, restarts are unlimited otherwise
+    pub fn burst(mut self, count: u32, interval: Duration) -> Self {
+        self.burst = Some((count, interval));
+        self
+    }
+
+    /// Grows the delay exponentially up to `max_delay`, reached after `steps` restarts
+    pub fn backoff(mut self, max_delay: Duration, steps: u32) -> Self {
+        self.backoff = Some((max_delay, steps));
+        self
+    }
+
+    /// Exit code considered a clean exit, besides 0
+    pub fn success_exit_code(mut self, code: i32) -> Self {
+        self.success_exit_codes.push(code);
+        self
+    }
+
+    /// Exit code that never triggers a restart
+    pub fn prevent_exit_code(mut self, code: i32) -> Self {
+        self.prevent_exit_codes.push(code);
+        self
+    }
+
+    // Follows systemd: the delay grows exponentially from RestartSec up to the max delay
+    pub(crate) fn delays(&self) -> Vec<Duration> {
+        let delay = self.delay.unwrap_or(DEFAULT_DELAY);
+        match self.backoff {
+            Some((max_delay, steps)) if steps > 0 && max_delay > delay => {
+                let ratio = max_delay.as_secs_f64() / delay.as_secs_f64().max(0.001);
+                (0..=steps)
+                    .map(|i| delay.max(Duration::from_millis(1))
+                        .mul_f64(ratio.powf(i as f64 / steps as f64)))
+                    .collect()
+            },
+            _ => vec![delay],
+        }
+    }
+
+    /// Exit code 0 or one of the success exit codes, `None` stands for a signal
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    pub(crate) fn is_success(&self, code: Option<i32>) -> bool {
+        code.is_some_and(|code| code == 0 || self.success_exit_codes.contains(&code))
+    }
+
+    /// Whether a process that exited with `code`, or was killed by a signal when `None`,
+    /// is restarted
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    pub(crate) fn restarts_after(&self, code: Option<i32>) -> bool {
+        if code.is_some_and(|code| self.prevent_exit_codes.contains(&code)) {
+            return false;
+        }
+        match self.mode {
+            RestartMode::Never => false,
+            RestartMode::Always => true,
+            RestartMode::OnFailure => !self.is_success(code),
+            RestartMode::OnAbnormal => code.is_none(),
+        }
+    }
+}
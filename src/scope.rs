@@ -0,0 +1,19 @@
+/// Service manager instance a service belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Scope {
+    /// Managed by the system, needs root
+    #[default]
+    System,
+    /// Managed by the per-user instance of the current user, runs without root
+    User,
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scope = match self {
+            Scope::System => "system",
+            Scope::User => "user",
+        };
+        write!(f, "{}", scope)
+    }
+}
@@ -0,0 +1,313 @@
+use crate::{Liveness, LogFiles, Readiness, RestartPolicy, Scope, StartupMode};
+use std::path::{Path, PathBuf};
+
+/// Declarative description of a service, consumed by `Sombra::from_spec`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceSpec {
+    pub(crate) name: String,
+    pub(crate) executable: PathBuf,
+    pub(crate) args: Vec<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) startup: StartupMode,
+    pub(crate) env: Vec<(String, String)>,
+    pub(crate) env_file: Option<PathBuf>,
+    pub(crate) working_directory: Option<WorkingDirectory>,
+    pub(crate) restart: RestartPolicy,
+    pub(crate) user: Option<String>,
+    pub(crate) group: Option<String>,
+    pub(crate) supplementary_groups: Vec<String>,
+    pub(crate) ambient_capabilities: Vec<String>,
+    pub(crate) readiness: Option<Readiness>,
+    pub(crate) liveness: Option<Liveness>,
+    pub(crate) log_files: Option<LogFiles>,
+    pub(crate) scope: Scope,
+    pub(crate) linger: bool,
+    pub(crate) root: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum WorkingDirectory {
+    Path(PathBuf),
+    /// Directory containing the service executable
+    Executable,
+}
+
+impl ServiceSpec {
+    pub fn new(name: &str, executable: &str) -> Self {
+        ServiceSpec {
+            name: name.to_string(),
+            executable: PathBuf::from(executable),
+            args: vec![],
+            description: None,
+            startup: StartupMode::default(),
+            env: vec![],
+            env_file: None,
+            working_directory: None,
+            restart: RestartPolicy::default(),
+            user: None,
+            group: None,
+            supplementary_groups: vec![],
+            ambient_capabilities: vec![],
+            readiness: None,
+            liveness: None,
+            log_files: None,
+            scope: Scope::default(),
+            linger: false,
+            root: None,
+        }
+    }
+
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args.extend(args);
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn startup(mut self, mode: StartupMode) -> Self {
+        self.startup = mode;
+        self
+    }
+
+    /// Sets an environment variable of the service process
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.retain(|(k, _)| k != key);
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Loads environment variables from a dotenv-style file when the service is created,
+    /// variables set with `env` take precedence
+    pub fn env_file(mut self, path: &str) -> Self {
+        self.env_file = Some(PathBuf::from(path));
+        self
+    }
+
+    /// Copies a variable from the environment of the calling process, if it is set
+    pub fn inherit_env(self, key: &str) -> Self {
+        match std::env::var(key) {
+            Ok(value) => self.env(key, &value),
+            Err(_) => self,
+        }
+    }
+
+    /// Directory the service process runs from, it must exist when the service is built
+    pub fn working_directory(mut self, path: &str) -> Self {
+        self.working_directory = Some(WorkingDirectory::Path(PathBuf::from(path)));
+        self
+    }
+
+    /// Runs the service process from the directory containing its executable
+    pub fn working_directory_from_executable(mut self) -> Self {
+        self.working_directory = Some(WorkingDirectory::Executable);
+        self
+    }
+
+    pub fn restart(mut self, policy: RestartPolicy) -> Self {
+        self.restart = policy;
+        self
+    }
+
+    /// Account the service process runs as, it must exist when the service is built
+    pub fn user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Primary group of the service process, defaults to the group of its user
+    pub fn group(mut self, group: &str) -> Self {
+        self.group = Some(group.to_string());
+        self
+    }
+
+    pub fn supplementary_group(mut self, group: &str) -> Self {
+        self.supplementary_groups.push(group.to_string());
+        self
+    }
+
+    /// Grants a capability such as `CAP_NET_BIND_SERVICE` to a service running without root
+    pub fn ambient_capability(mut self, capability: &str) -> Self {
+        self.ambient_capabilities.push(capability.to_string());
+        self
+    }
+
+    /// Probe that has to pass before `create` and `start` return
+    pub fn readiness(mut self, readiness: Readiness) -> Self {
+        self.readiness = Some(readiness);
+        self
+    }
+
+    /// Probe run by `sombra monitor` while the service is running
+    pub fn liveness(mut self, liveness: Liveness) -> Self {
+        self.liveness = Some(liveness);
+        self
+    }
+
+    /// Writes stdout and stderr to files instead of the system log
+    pub fn log_files(mut self, log_files: LogFiles) -> Self {
+        self.log_files = Some(log_files);
+        self
+    }
+
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// Keeps the user services running after the user logs out, for `Scope::User`
+    pub fn linger(mut self) -> Self {
+        self.linger = true;
+        self
+    }
+
+    /// Installs the service into the filesystem tree at `path`, such as the staging root of
+    /// an image, without contacting the running service manager. Paths of the service refer
+    /// to that tree, and it is enabled for boot but never started
+    pub fn root(mut self, path: &str) -> Self {
+        self.root = Some(PathBuf::from(path));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn executable(&self) -> &Path {
+        &self.executable
+    }
+
+    /// Environment of the service process, merging the env file with explicit variables
+    pub(crate) fn environment(&self) -> crate::Result<Vec<(String, String)>> {
+        let mut environment = match &self.env_file {
+            Some(env_file) => crate::env::read_env_file(&self.host_path(env_file))?,
+            None => vec![],
+        };
+        environment.retain(|(key, _)| self.env.iter().all(|(k, _)| k != key));
+        environment.extend(self.env.iter().cloned());
+        Ok(environment)
+    }
+
+    /// Where a path of the service is found from this process, inside the root if there is one
+    pub(crate) fn host_path(&self, path: &Path) -> PathBuf {
+        match &self.root {
+            Some(root) => root.join(path.strip_prefix("/").unwrap_or(path)),
+            None => path.to_path_buf(),
+        }
+    }
+
+    pub(crate) fn canonicalize(mut self) -> crate::Result<Self> {
+        crate::ServiceName::new(&self.name)?;
+        if let Some(root) = self.root.take() {
+            self.root = Some(directory(&root)?);
+            return self.canonicalize_in_root();
+        }
+        self.executable = canonicalize(&self.executable)?;
+        if let Some(env_file) = &self.env_file {
+            self.env_file = Some(canonicalize(env_file)?);
+        }
+        self.working_directory = match self.working_directory.take() {
+            Some(WorkingDirectory::Path(path)) => Some(WorkingDirectory::Path(directory(&path)?)),
+            Some(WorkingDirectory::Executable) => self.executable.parent()
+                .map(|parent| WorkingDirectory::Path(parent.to_path_buf())),
+            None => None,
+        };
+        self.log_files = match self.log_files.take() {
+            Some(log_files) => Some(log_files.canonicalize()?),
+            None => None,
+        };
+        self.validate()
+    }
+
+    // Paths are only made absolute, since symlinks of the image would resolve on the host
+    fn canonicalize_in_root(mut self) -> crate::Result<Self> {
+        self.executable = self.existing_in_root(&self.executable)?;
+        if let Some(env_file) = &self.env_file {
+            self.env_file = Some(self.existing_in_root(env_file)?);
+        }
+        self.working_directory = match self.working_directory.take() {
+            Some(WorkingDirectory::Path(path)) => {
+                let path = self.existing_in_root(&path)?;
+                if !self.host_path(&path).is_dir() {
+                    return Err(crate::Error::new(crate::ErrorKind::Io,
+                                                 "Not a directory".to_string())
+                        .content(path.display().to_string()));
+                }
+                Some(WorkingDirectory::Path(path))
+            },
+            Some(WorkingDirectory::Executable) => self.executable.parent()
+                .map(|parent| WorkingDirectory::Path(parent.to_path_buf())),
+            None => None,
+        };
+        if let Some(log_files) = &mut self.log_files {
+            log_files.stdout = Path::new("/").join(&log_files.stdout);
+            log_files.stderr = log_files.stderr.as_ref().map(|stderr| Path::new("/").join(stderr));
+        }
+        self.validate()
+    }
+
+    // Relative paths start from the root
+    fn existing_in_root(&self, path: &Path) -> crate::Result<PathBuf> {
+        let path = Path::new("/").join(path);
+        if self.host_path(&path).exists() {
+            Ok(path)
+        } else {
+            Err(crate::Error::new(crate::ErrorKind::Io, "Not found in root".to_string())
+                .content(self.host_path(&path).display().to_string()))
+        }
+    }
+
+    fn validate(mut self) -> crate::Result<Self> {
+        for (key, _) in &self.env {
+            crate::env::check_name(key)?;
+        }
+        self.ambient_capabilities = self.ambient_capabilities.iter()
+            .map(|name| capability(name))
+            .collect::<crate::Result<_>>()?;
+        if let Some(readiness) = &self.readiness {
+            readiness.probe.validate()?;
+        }
+        if let Some(liveness) = &self.liveness {
+            liveness.validate()?;
+        }
+        if let Some(log_files) = &self.log_files {
+            log_files.validate()?;
+        }
+        Ok(self)
+    }
+}
+
+fn canonicalize(path: &Path) -> crate::Result<PathBuf> {
+    dunce::canonicalize(path)
+        .map_err(|e| crate::Error::new(crate::ErrorKind::Io, e.to_string())
+            .content(path.display().to_string()))
+}
+
+fn directory(path: &Path) -> crate::Result<PathBuf> {
+    let path = canonicalize(path)?;
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err(crate::Error::new(crate::ErrorKind::Io, "Not a directory".to_string())
+            .content(path.display().to_string()))
+    }
+}
+
+// Accepts `net_bind_service` as well as `CAP_NET_BIND_SERVICE`
+fn capability(name: &str) -> crate::Result<String> {
+    let name = name.to_ascii_uppercase();
+    let name = if name.starts_with("CAP_") { name } else { format!("CAP_{}", name) };
+    if name.len() > 4 && name.chars().all(|c| c.is_ascii_uppercase() || c == '_') {
+        Ok(name)
+    } else {
+        Err(crate::Error::new(crate::ErrorKind::Other,
+                              format!("Invalid capability name {:?}", name)))
+    }
+}
@@ -0,0 +1,36 @@
+/// How a service behaves when the system boots
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StartupMode {
+    /// Started only on request
+    #[default]
+    Manual,
+    /// Started automatically on boot
+    Boot,
+    /// Installed but not started, neither on creation nor on boot
+    Disabled,
+}
+
+impl std::str::FromStr for StartupMode {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> crate::Result<Self> {
+        match s {
+            "manual" => Ok(StartupMode::Manual),
+            "boot" => Ok(StartupMode::Boot),
+            "disabled" => Ok(StartupMode::Disabled),
+            _ => Err(crate::Error::new(crate::ErrorKind::Other,
+                                       format!("Unknown startup mode {}, expected manual, boot or disabled", s))),
+        }
+    }
+}
+
+impl std::fmt::Display for StartupMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mode = match self {
+            StartupMode::Manual => "manual",
+            StartupMode::Boot => "boot",
+            StartupMode::Disabled => "disabled",
+        };
+        write!(f, "{}", mode)
+    }
+}
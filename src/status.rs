@@ -0,0 +1,50 @@
+use crate::Health;
+use std::time::Duration;
+
+/// State of a service as seen by the platform service manager
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ServiceState {
+    Running,
+    Stopped,
+    Failed,
+    NotInstalled,
+}
+
+/// Snapshot of a service returned by `Sombra::status`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceStatus {
+    pub state: ServiceState,
+    pub main_pid: Option<u32>,
+    pub uptime: Option<Duration>,
+    pub last_exit_code: Option<i32>,
+    /// Last liveness result, when `sombra monitor` probes the service
+    pub health: Option<Health>,
+}
+
+impl ServiceStatus {
+    pub fn new(state: ServiceState) -> Self {
+        ServiceStatus {
+            state,
+            main_pid: None,
+            uptime: None,
+            last_exit_code: None,
+            health: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == ServiceState::Running
+    }
+}
+
+impl std::fmt::Display for ServiceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = match self {
+            ServiceState::Running => "running",
+            ServiceState::Stopped => "stopped",
+            ServiceState::Failed => "failed",
+            ServiceState::NotInstalled => "not installed",
+        };
+        write!(f, "{}", state)
+    }
+}
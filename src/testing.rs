@@ -0,0 +1,355 @@
+//! In-memory backend for testing code that uses sombra, without a service manager or root
+//!
+//! Services live in a registry local to the current thread, so tests running in parallel
+//! do not see each other. Code under test has to call sombra from the thread of the test.
+//!
+//! ```
+//! use sombra::{Sombra, ServiceState, ServiceSpec};
+//! use sombra::testing::{MockSombra, Operation};
+//!
+//! let service = MockSombra::from_spec(ServiceSpec::new("tcp_echo", "/usr/bin/tcp_echo")).unwrap();
+//! service.create().unwrap();
+//! MockSombra::crash("tcp_echo", 1);
+//! assert_eq!(service.status().unwrap().state, ServiceState::Failed);
+//! assert_eq!(MockSombra::operations(), vec![("tcp_echo".to_string(), Operation::Create),
+//!                                           ("tcp_echo".to_string(), Operation::Status)]);
+//! ```
+
+use crate::{LogLine, LogQuery, Logs, Scope, ServiceSpec, ServiceState, ServiceStatus, Sombra,
+            StartupMode};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+/// Call made through the `Sombra` trait, as recorded by the mock
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Create,
+    Delete,
+    ForceDelete,
+    Start,
+    Stop,
+    Restart,
+    Enable,
+    Disable,
+    Status,
+    Logs,
+}
+
+struct Service {
+    spec: ServiceSpec,
+    status: ServiceStatus,
+    enabled: bool,
+    managed: bool,
+    logs: Vec<LogLine>,
+}
+
+#[derive(Default)]
+struct Registry {
+    services: BTreeMap<String, Service>,
+    operations: Vec<(String, Operation)>,
+    failures: Vec<(String, Operation, crate::Error)>,
+    next_pid: u32,
+}
+
+thread_local! {
+    static REGISTRY: RefCell<Registry> = RefCell::new(Registry::default());
+}
+
+fn with_registry<T, F: FnOnce(&mut Registry) -> T>(f: F) -> T {
+    REGISTRY.with(|registry| f(&mut registry.borrow_mut()))
+}
+
+impl Registry {
+    // Records the call, then hands out the failure injected for it, if any
+    fn call(&mut self, name: &str, operation: Operation) -> crate::Result<()> {
+        self.operations.push((name.to_string(), operation));
+        match self.failures.iter().position(|(n, o, _)| n == name && *o == operation) {
+            Some(index) => Err(self.failures.remove(index).2),
+            None => Ok(()),
+        }
+    }
+
+    fn service(&mut self, name: &str) -> crate::Result<&mut Service> {
+        self.services.get_mut(name)
+            .ok_or_else(|| crate::Error::new(crate::ErrorKind::Io,
+                                             format!("Service {} does not exist", name)))
+    }
+
+    fn run(&mut self, name: &str) -> crate::Result<()> {
+        self.next_pid += 1;
+        let pid = 1000 + self.next_pid;
+        let service = self.service(name)?;
+        service.status = ServiceStatus::new(ServiceState::Running);
+        service.status.main_pid = Some(pid);
+        Ok(())
+    }
+}
+
+/// Service kept in memory, following the state transitions of a real service manager
+pub struct MockSombra {
+    spec: ServiceSpec,
+}
+
+impl MockSombra {
+    /// Forgets every service, operation and pending failure of the current thread
+    pub fn reset() {
+        with_registry(|registry| *registry = Registry::default());
+    }
+
+    /// Operations called so far, oldest first, with the name of the service
+    pub fn operations() -> Vec<(String, Operation)> {
+        with_registry(|registry| registry.operations.clone())
+    }
+
+    /// Makes the next `operation` on the service fail with `error`
+    pub fn fail_next(name: &str, operation: Operation, error: crate::Error) {
+        with_registry(|registry| registry.failures.push((name.to_string(), operation, error)));
+    }
+
+    /// Stops the process of a running service as if it exited on its own with `exit_code`
+    pub fn crash(name: &str, exit_code: i32) {
+        with_registry(|registry| if let Some(service) = registry.services.get_mut(name) {
+            service.status = ServiceStatus::new(ServiceState::Failed);
+            service.status.last_exit_code = Some(exit_code);
+        });
+    }
+
+    /// Adds a line to what `logs` returns for the service
+    pub fn log(name: &str, message: &str) {
+        with_registry(|registry| if let Some(service) = registry.services.get_mut(name) {
+            service.logs.push(LogLine {
+                timestamp: Some(SystemTime::now()),
+                priority: None,
+                message: message.to_string(),
+            });
+        });
+    }
+
+    /// Installs a service that sombra did not create, which only `force_delete` removes
+    pub fn install_foreign(name: &str) {
+        with_registry(|registry| {
+            registry.services.insert(name.to_string(), Service {
+                spec: ServiceSpec::new(name, ""),
+                status: ServiceStatus::new(ServiceState::Stopped),
+                enabled: false,
+                managed: false,
+                logs: vec![],
+            });
+        });
+    }
+
+    /// State of the service, `None` once it is deleted
+    pub fn state(name: &str) -> Option<ServiceState> {
+        with_registry(|registry| registry.services.get(name).map(|s| s.status.state))
+    }
+
+    pub fn is_enabled(name: &str) -> bool {
+        with_registry(|registry| registry.services.get(name).is_some_and(|s| s.enabled))
+    }
+
+    fn call<T, F>(&self, operation: Operation, f: F) -> crate::Result<T>
+        where F: FnOnce(&mut Registry) -> crate::Result<T> {
+        with_registry(|registry| {
+            registry.call(&self.spec.name, operation)?;
+            f(registry)
+        })
+    }
+
+    fn remove(&self, registry: &mut Registry, force: bool) -> crate::Result<()> {
+        let name = &self.spec.name;
+        if !force && !registry.service(name)?.managed {
+            return Err(crate::Error::new(crate::ErrorKind::NotManaged,
+                                         format!("Service {} was not created by sombra", name)));
+        }
+        registry.services.remove(name);
+        Ok(())
+    }
+}
+
+impl Sombra for MockSombra {
+    /// Only the name is checked, paths do not have to exist
+    fn from_spec(spec: ServiceSpec) -> crate::Result<Self> {
+        crate::ServiceName::new(&spec.name)?;
+        Ok(MockSombra { spec })
+    }
+
+    fn list_in(scope: Scope) -> crate::Result<Vec<Self>> {
+        Ok(with_registry(|registry| registry.services.values()
+            .filter(|service| service.managed && service.spec.scope == scope)
+            .map(|service| MockSombra { spec: service.spec.clone() })
+            .collect()))
+    }
+
+    fn spec(&self) -> &ServiceSpec {
+        &self.spec
+    }
+
+    fn create(&self) -> crate::Result<()> {
+        self.call(Operation::Create, |registry| {
+            let name = &self.spec.name;
+            if registry.services.contains_key(name) {
+                return Err(crate::Error::new(crate::ErrorKind::Io,
+                                             format!("Service {} already exist", name)));
+            }
+            registry.services.insert(name.clone(), Service {
+                spec: self.spec.clone(),
+                status: ServiceStatus::new(ServiceState::Stopped),
+                enabled: self.spec.startup == StartupMode::Boot,
+                managed: true,
+                logs: vec![],
+            });
+            match self.spec.startup {
+                StartupMode::Disabled => Ok(()),
+                StartupMode::Boot | StartupMode::Manual => registry.run(name),
+            }
+        })
+    }
+
+    fn delete(&self) -> crate::Result<()> {
+        self.call(Operation::Delete, |registry| self.remove(registry, false))
+    }
+
+    fn force_delete(&self) -> crate::Result<()> {
+        self.call(Operation::ForceDelete, |registry| self.remove(registry, true))
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        self.call(Operation::Start, |registry| {
+            // Like systemctl, starting a running service leaves it alone
+            if registry.service(&self.spec.name)?.status.is_running() {
+                return Ok(());
+            }
+            registry.run(&self.spec.name)
+        })
+    }
+
+    fn stop(&self) -> crate::Result<()> {
+        self.call(Operation::Stop, |registry| {
+            let service = registry.service(&self.spec.name)?;
+            let last_exit_code = service.status.last_exit_code;
+            service.status = ServiceStatus::new(ServiceState::Stopped);
+            service.status.last_exit_code = last_exit_code;
+            Ok(())
+        })
+    }
+
+    fn restart(&self) -> crate::Result<()> {
+        self.call(Operation::Restart, |registry| registry.run(&self.spec.name))
+    }
+
+    fn enable(&self) -> crate::Result<()> {
+        self.call(Operation::Enable, |registry| {
+            registry.service(&self.spec.name)?.enabled = true;
+            Ok(())
+        })
+    }
+
+    fn disable(&self) -> crate::Result<()> {
+        self.call(Operation::Disable, |registry| {
+            registry.service(&self.spec.name)?.enabled = false;
+            Ok(())
+        })
+    }
+
+    fn status(&self) -> crate::Result<ServiceStatus> {
+        self.call(Operation::Status, |registry| Ok(match registry.services.get(&self.spec.name) {
+            Some(service) => service.status.clone(),
+            None => ServiceStatus::new(ServiceState::NotInstalled),
+        }))
+    }
+
+    /// Returns the lines added with `MockSombra::log`, following does not wait for more
+    fn logs(&self, query: &LogQuery) -> crate::Result<Logs> {
+        self.call(Operation::Logs, |registry| {
+            let mut lines: Vec<LogLine> = registry.service(&self.spec.name)?.logs.iter()
+                .filter(|line| match (line.timestamp, query.since) {
+                    (Some(timestamp), Some(since)) => timestamp >= since,
+                    _ => true,
+                })
+                .filter(|line| match (line.timestamp, query.until) {
+                    (Some(timestamp), Some(until)) => timestamp <= until,
+                    _ => true,
+                })
+                .cloned()
+                .collect();
+            if let Some(count) = query.lines {
+                lines.drain(..lines.len().saturating_sub(count));
+            }
+            Ok(Logs::new(lines.into_iter().map(Ok)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    fn mock(name: &str) -> MockSombra {
+        match MockSombra::from_spec(ServiceSpec::new(name, "/usr/bin/tcp_echo")) {
+            Ok(mock) => mock,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn follows_lifecycle() {
+        MockSombra::reset();
+        let s = mock("tcp_echo");
+        assert_eq!(s.status().map(|status| status.state), Ok(ServiceState::NotInstalled));
+        assert_eq!(s.create(), Ok(()));
+        assert_eq!(s.create().map_err(|e| e.to_string()),
+                   Err("<Io> Service tcp_echo already exist".to_string()));
+        assert_eq!(MockSombra::state("tcp_echo"), Some(ServiceState::Running));
+
+        MockSombra::crash("tcp_echo", 3);
+        let status = s.status();
+        assert_eq!(status.as_ref().map(|status| status.state), Ok(ServiceState::Failed));
+        assert_eq!(status.map(|status| status.last_exit_code), Ok(Some(3)));
+        assert_eq!(s.restart(), Ok(()));
+        assert_eq!(s.stop(), Ok(()));
+        assert_eq!(MockSombra::state("tcp_echo"), Some(ServiceState::Stopped));
+        assert_eq!(s.enable(), Ok(()));
+        assert!(MockSombra::is_enabled("tcp_echo"));
+
+        assert_eq!(MockSombra::list().map(|list| list.len()), Ok(1));
+        assert_eq!(s.delete(), Ok(()));
+        assert!(s.delete().is_err());
+        assert_eq!(MockSombra::state("tcp_echo"), None);
+        assert_eq!(MockSombra::operations().len(), 9);
+        assert_eq!(MockSombra::operations().last(),
+                   Some(&("tcp_echo".to_string(), Operation::Delete)));
+    }
+
+    #[test]
+    fn injects_failures() {
+        MockSombra::reset();
+        let s = mock("tcp_echo");
+        MockSombra::fail_next("tcp_echo", Operation::Create,
+                              crate::Error::new(ErrorKind::ServiceManager, "boom".to_string()));
+        assert_eq!(s.create().map_err(|e| e.to_string()),
+                   Err("<ServiceManager> boom".to_string()));
+        assert_eq!(MockSombra::state("tcp_echo"), None);
+        assert_eq!(s.create(), Ok(()));
+
+        MockSombra::install_foreign("sshd");
+        let foreign = mock("sshd");
+        assert_eq!(foreign.delete().map_err(|e| *e.kind() == ErrorKind::NotManaged), Err(true));
+        assert_eq!(foreign.force_delete(), Ok(()));
+        assert_eq!(MockSombra::list().map(|list| list.len()), Ok(1));
+    }
+
+    #[test]
+    fn returns_logged_lines() {
+        MockSombra::reset();
+        let s = mock("tcp_echo");
+        assert_eq!(s.create(), Ok(()));
+        for message in &["one", "two", "three"] {
+            MockSombra::log("tcp_echo", message);
+        }
+        let lines: crate::Result<Vec<String>> = s.logs(&LogQuery::new().lines(2))
+            .and_then(|logs| logs.map(|line| line.map(|line| line.message)).collect());
+        assert_eq!(lines, Ok(vec!["two".to_string(), "three".to_string()]));
+    }
+}
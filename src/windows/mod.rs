@@ -1 +1,2 @@
-pub mod sombra_imp;
\ No newline at end of file
+pub mod sombra_imp;
+mod parameters;
@@ -0,0 +1,85 @@
+use crate::marker::Marker;
+use winreg::{RegKey, RegValue};
+use winreg::enums::{HKEY_LOCAL_MACHINE, KEY_SET_VALUE, RegType};
+
+const SERVICES_KEY: &str = "SYSTEM\\CurrentControlSet\\Services";
+const MARKER_VALUE: &str = "Sombra";
+
+// The wrapper receives the target process as start arguments, so the spec is kept
+// in the conventional `Parameters` key of the service, as the ownership marker
+fn key_path(name: &str) -> String {
+    format!("{}\\{}\\Parameters", SERVICES_KEY, name)
+}
+
+fn to_multi_sz(values: &[String]) -> RegValue {
+    let mut words: Vec<u16> = vec![];
+    for value in values {
+        words.extend(value.encode_utf16());
+        words.push(0);
+    }
+    words.push(0);
+
+    RegValue {
+        bytes: words.iter().flat_map(|w| w.to_le_bytes().to_vec()).collect(),
+        vtype: RegType::REG_MULTI_SZ,
+    }
+}
+
+fn from_multi_sz(value: &RegValue) -> Vec<String> {
+    let words: Vec<u16> = value.bytes.chunks(2)
+        .map(|pair| u16::from_le_bytes([pair[0], *pair.get(1).unwrap_or(&0)]))
+        .collect();
+    String::from_utf16_lossy(&words)
+        .split('\u{0}')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+pub fn save(marker: &Marker) -> crate::Result<()> {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let (key, _) = hklm.create_subkey(key_path(&marker.spec.name))?;
+    let entries: Vec<String> = marker.entries().iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    key.set_raw_value(MARKER_VALUE, &to_multi_sz(&entries))?;
+    Ok(())
+}
+
+// The service control manager passes this value as the environment of the service process
+pub fn save_environment(name: &str, environment: &[(String, String)]) -> crate::Result<()> {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let key = hklm.open_subkey_with_flags(format!("{}\\{}", SERVICES_KEY, name), KEY_SET_VALUE)?;
+    let entries: Vec<String> = environment.iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    key.set_raw_value("Environment", &to_multi_sz(&entries))?;
+    Ok(())
+}
+
+pub fn load(name: &str) -> crate::Result<Option<Marker>> {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let value = match hklm.open_subkey(key_path(name))
+        .and_then(|key| key.get_raw_value(MARKER_VALUE)) {
+        Ok(value) => value,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let entries = from_multi_sz(&value);
+    Ok(Marker::from_entries(name, entries.iter().filter_map(|entry| {
+        let mut pair = entry.splitn(2, '=');
+        Some((pair.next()?, pair.next()?))
+    })))
+}
+
+pub fn managed_services() -> crate::Result<Vec<Marker>> {
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let mut services = vec![];
+    for name in hklm.open_subkey(SERVICES_KEY)?.enum_keys() {
+        if let Some(marker) = load(&name?)? {
+            services.push(marker);
+        }
+    }
+    Ok(services)
+}
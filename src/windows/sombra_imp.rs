@@ -1,18 +1,19 @@
-use crate::Sombra;
+use crate::{Sombra, LogQuery, Logs, Probe, RestartMode, Scope, RestartPolicy, ServiceSpec, ServiceStatus, StartupMode};
+use crate::windows::parameters;
+use crate::marker::Marker;
 use std::ffi::{OsString, OsStr};
 use windows_service::{
-    service::{ServiceAccess, ServiceState, ServiceErrorControl, ServiceInfo,
+    service::{ServiceAccess, ServiceAction, ServiceActionType, ServiceState, ServiceErrorControl,
+              ServiceExitCode, ServiceFailureActions, ServiceFailureResetPeriod, ServiceInfo,
               ServiceStartType, ServiceType},
     service_manager::{ServiceManager, ServiceManagerAccess}
 };
 use std::time::Duration;
-use std::path::PathBuf;
 
-pub struct SombraWindows {
-    process_path: PathBuf,
-    process_name: String,
-    process_args: Vec<String>,
-}
+// ERROR_SERVICE_DOES_NOT_EXIST
+const SERVICE_DOES_NOT_EXIST: i32 = 1060;
+const STOP_POLL_ATTEMPTS: u32 = 50;
+const FAILURE_RESET_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
 
 macro_rules! sombra_error {
     ($kind:ident, $content:expr) => {
@@ -20,23 +21,25 @@ macro_rules! sombra_error {
     };
 }
 
-impl Sombra for SombraWindows {
-    fn build(name: &str, path: &str, args: Vec<String>) -> crate::Result<Self> {
-        let path = dunce::canonicalize(path)
-            .map_err(sombra_error!(Io, path.to_string()))?;
+pub struct SombraWindows {
+    spec: ServiceSpec,
+}
 
-        Ok(SombraWindows {
-            process_path: path,
-            process_name: name.to_string(),
-            process_args: args,
-        })
+impl SombraWindows {
+    fn user_scope() -> crate::Error {
+        crate::Error::new(crate::ErrorKind::Unsupported,
+                          "User services are not supported on Windows".to_string())
     }
 
-    fn create(&self) -> crate::Result<()> {
-        let manager_access = ServiceManagerAccess::CONNECT |
-            ServiceManagerAccess::CREATE_SERVICE;
-        let service_manager = ServiceManager::local_computer(None::<&str>,
-                                                             manager_access)?;
+    fn start_type(mode: StartupMode) -> ServiceStartType {
+        match mode {
+            StartupMode::Manual => ServiceStartType::OnDemand,
+            StartupMode::Boot => ServiceStartType::AutoStart,
+            StartupMode::Disabled => ServiceStartType::Disabled,
+        }
+    }
+
+    fn service_info(&self, start_type: ServiceStartType) -> crate::Result<ServiceInfo> {
         if std::env::var("SOMBRA_WINDOWS_SERVICE_PATH").is_err() {
             std::env::set_var("SOMBRA_WINDOWS_SERVICE_PATH",
                               "executables/sombra-windows-service.exe");
@@ -45,42 +48,67 @@ impl Sombra for SombraWindows {
         let service_binary_path = dunce::canonicalize(&sombra_win_service)
             .map_err(sombra_error!(Io, sombra_win_service.clone()))?;
 
-        let service_info = ServiceInfo {
-            name: OsString::from(self.process_name.clone()),
-            display_name: OsString::from(self.process_name.clone()),
+        Ok(ServiceInfo {
+            name: OsString::from(self.spec.name.clone()),
+            display_name: OsString::from(self.spec.name.clone()),
             service_type: ServiceType::OWN_PROCESS,
-            start_type: ServiceStartType::OnDemand,
+            start_type,
             error_control: ServiceErrorControl::Normal,
-            executable_path: PathBuf::from(service_binary_path),
+            executable_path: service_binary_path,
             launch_arguments: vec![],
             dependencies: vec![],
-            account_name: None, // run as System
+            // Run as System unless an account is chosen, only accounts without a password such
+            // as NT AUTHORITY\LocalService can be used
+            account_name: self.spec.user.as_ref().map(OsString::from),
             account_password: None,
+        })
+    }
+
+    fn failure_actions(policy: &RestartPolicy) -> ServiceFailureActions {
+        let restart = |delay| ServiceAction {
+            action_type: ServiceActionType::Restart,
+            delay,
+        };
+        let delays = policy.delays();
+        // The last action is repeated for every further failure
+        let (reset_period, actions) = match policy.burst {
+            Some((count, interval)) => {
+                let last = delays[delays.len() - 1];
+                let mut actions: Vec<_> = (0..count as usize)
+                    .map(|i| restart(*delays.get(i).unwrap_or(&last)))
+                    .collect();
+                actions.push(ServiceAction {
+                    action_type: ServiceActionType::None,
+                    delay: Duration::default(),
+                });
+                (ServiceFailureResetPeriod::After(interval), actions)
+            },
+            None => (ServiceFailureResetPeriod::After(FAILURE_RESET_PERIOD),
+                     delays.into_iter().map(restart).collect()),
         };
-        let service = service_manager.create_service(&service_info,
-                                                     ServiceAccess::CHANGE_CONFIG)?;
-        service.set_description(format!("Sombra Service Wrapper on {}", self.process_name))?;
 
-        let service_access = ServiceAccess::START;
-        let service = service_manager.open_service(&self.process_name,
-                                                   service_access)?;
-        let mut args = vec![OsStr::new(&self.process_path)];
-        for a in &self.process_args {
-            args.push(a.as_ref());
+        ServiceFailureActions {
+            reset_period,
+            reboot_msg: None,
+            command: None,
+            actions: Some(actions),
         }
-        service.start(&args)?;
-
-        Ok(())
     }
 
-    fn delete(&self) -> crate::Result<()> {
+    fn remove(&self, force: bool) -> crate::Result<()> {
         let manager_access = ServiceManagerAccess::CONNECT;
         let service_manager = ServiceManager::local_computer(None::<&str>,
                                                              manager_access)?;
         let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP |
             ServiceAccess::DELETE;
-        let service = service_manager.open_service(&self.process_name,
+        let service = service_manager.open_service(&self.spec.name,
                                                    service_access)?;
+        if !force && parameters::load(&self.spec.name)?.is_none() {
+            return Err(crate::Error::new(crate::ErrorKind::NotManaged,
+                                         format!("Service {} was not created by sombra",
+                                                 self.spec.name)));
+        }
+
         let service_status = service.query_status()?;
         if service_status.current_state != ServiceState::Stopped {
             service.stop()?;
@@ -91,10 +119,221 @@ impl Sombra for SombraWindows {
 
         Ok(())
     }
+
+    fn change_start_type(&self, start_type: ServiceStartType) -> crate::Result<()> {
+        let manager_access = ServiceManagerAccess::CONNECT;
+        let service_manager = ServiceManager::local_computer(None::<&str>,
+                                                             manager_access)?;
+        let service = service_manager.open_service(&self.spec.name,
+                                                   ServiceAccess::CHANGE_CONFIG)?;
+        service.change_config(&self.service_info(start_type)?)?;
+        Ok(())
+    }
+}
+
+impl Sombra for SombraWindows {
+    fn from_spec(spec: ServiceSpec) -> crate::Result<Self> {
+        if spec.scope != Scope::System {
+            return Err(SombraWindows::user_scope());
+        }
+        if spec.root.is_some() {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Installing into a root directory is not supported on \
+                                          Windows".to_string()));
+        }
+        // The wrapper offers no way to choose the directory of the target process
+        if spec.working_directory.is_some() {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Working directory is not supported on Windows"
+                                             .to_string()));
+        }
+        if spec.group.is_some() || !spec.supplementary_groups.is_empty()
+            || !spec.ambient_capabilities.is_empty() {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Groups and capabilities are not supported on Windows"
+                                             .to_string()));
+        }
+        // The wrapper owns the standard streams of the target process
+        if spec.log_files.is_some() {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Output files are not supported on Windows"
+                                             .to_string()));
+        }
+        if let Some(Probe::Log(_)) = spec.readiness.as_ref().map(|readiness| &readiness.probe) {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Log probes are not supported on Windows".to_string()));
+        }
+        // The service manager only sees the exit code of the wrapper
+        if !spec.restart.success_exit_codes.is_empty() || !spec.restart.prevent_exit_codes.is_empty() {
+            return Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                                         "Restart exit codes are not supported on Windows"
+                                             .to_string()));
+        }
+
+        Ok(SombraWindows {
+            spec: spec.canonicalize()?,
+        })
+    }
+
+    fn list_in(scope: Scope) -> crate::Result<Vec<Self>> {
+        if scope != Scope::System {
+            return Err(SombraWindows::user_scope());
+        }
+        let mut services: Vec<Self> = parameters::managed_services()?.into_iter()
+            .map(|marker| SombraWindows { spec: marker.spec })
+            .collect();
+        services.sort_by(|a, b| a.spec.name.cmp(&b.spec.name));
+
+        Ok(services)
+    }
+
+    fn spec(&self) -> &ServiceSpec {
+        &self.spec
+    }
+
+    fn create(&self) -> crate::Result<()> {
+        let manager_access = ServiceManagerAccess::CONNECT |
+            ServiceManagerAccess::CREATE_SERVICE;
+        let service_manager = ServiceManager::local_computer(None::<&str>,
+                                                             manager_access)?;
+        let service_info = self.service_info(SombraWindows::start_type(self.spec.startup))?;
+        let environment = self.spec.environment()?;
+        let service = service_manager.create_service(&service_info,
+                                                     ServiceAccess::CHANGE_CONFIG)?;
+        let description = match &self.spec.description {
+            Some(description) => description.clone(),
+            None => format!("Sombra Service Wrapper on {}", self.spec.name),
+        };
+        service.set_description(description)?;
+        // Windows has no "always": a clean exit is never treated as a failure
+        if self.spec.restart.mode != RestartMode::Never {
+            service.update_failure_actions(SombraWindows::failure_actions(&self.spec.restart))?;
+            service.set_failure_actions_on_non_crash_failures(
+                self.spec.restart.mode != RestartMode::OnAbnormal)?;
+        }
+        parameters::save(&Marker::new(&self.spec))?;
+        if !environment.is_empty() {
+            parameters::save_environment(&self.spec.name, &environment)?;
+        }
+
+        if self.spec.startup == StartupMode::Disabled {
+            return Ok(());
+        }
+        self.start()
+    }
+
+    fn delete(&self) -> crate::Result<()> {
+        self.remove(false)
+    }
+
+    fn force_delete(&self) -> crate::Result<()> {
+        self.remove(true)
+    }
+
+    fn start(&self) -> crate::Result<()> {
+        let manager_access = ServiceManagerAccess::CONNECT;
+        let service_manager = ServiceManager::local_computer(None::<&str>,
+                                                             manager_access)?;
+        let service = service_manager.open_service(&self.spec.name,
+                                                   ServiceAccess::START)?;
+
+        let spec = match parameters::load(&self.spec.name)? {
+            Some(marker) => marker.spec,
+            None => self.spec.clone(),
+        };
+        let mut args = vec![OsStr::new(&spec.executable)];
+        for a in &spec.args {
+            args.push(a.as_ref());
+        }
+        service.start(&args)?;
+
+        match &spec.readiness {
+            // Log probes are refused when the service is built
+            Some(readiness) => readiness.wait(&spec.name, || Err(crate::Error::new(
+                crate::ErrorKind::Unsupported, "Service logs are not available".to_string()))),
+            None => Ok(()),
+        }
+    }
+
+    fn stop(&self) -> crate::Result<()> {
+        let manager_access = ServiceManagerAccess::CONNECT;
+        let service_manager = ServiceManager::local_computer(None::<&str>,
+                                                             manager_access)?;
+        let service_access = ServiceAccess::QUERY_STATUS | ServiceAccess::STOP;
+        let service = service_manager.open_service(&self.spec.name,
+                                                   service_access)?;
+        if service.query_status()?.current_state == ServiceState::Stopped {
+            return Ok(());
+        }
+
+        service.stop()?;
+        // The wrapper has to terminate its child before reporting it stopped
+        for _ in 0..STOP_POLL_ATTEMPTS {
+            if service.query_status()?.current_state == ServiceState::Stopped {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        Err(crate::Error::new(crate::ErrorKind::WindowsService,
+                              format!("Service {} did not stop", self.spec.name)))
+    }
+
+    fn enable(&self) -> crate::Result<()> {
+        self.change_start_type(ServiceStartType::AutoStart)
+    }
+
+    fn disable(&self) -> crate::Result<()> {
+        // Mirrors `systemctl disable`: the service no longer starts on boot but can be started
+        self.change_start_type(ServiceStartType::OnDemand)
+    }
+
+    fn status(&self) -> crate::Result<ServiceStatus> {
+        let manager_access = ServiceManagerAccess::CONNECT;
+        let service_manager = ServiceManager::local_computer(None::<&str>,
+                                                             manager_access)?;
+        let service = match service_manager.open_service(&self.spec.name,
+                                                         ServiceAccess::QUERY_STATUS) {
+            Ok(service) => service,
+            Err(windows_service::Error::Winapi(e))
+                if e.raw_os_error() == Some(SERVICE_DOES_NOT_EXIST) => {
+                return Ok(ServiceStatus::new(crate::ServiceState::NotInstalled));
+            },
+            Err(e) => return Err(e.into()),
+        };
+        let service_status = service.query_status()?;
+
+        let exit_code = match service_status.exit_code {
+            ServiceExitCode::Win32(code) => code as i32,
+            ServiceExitCode::ServiceSpecific(code) => code as i32,
+        };
+        let state = match service_status.current_state {
+            ServiceState::Stopped if exit_code != 0 => crate::ServiceState::Failed,
+            ServiceState::Stopped | ServiceState::StopPending => crate::ServiceState::Stopped,
+            _ => crate::ServiceState::Running,
+        };
+
+        let mut status = ServiceStatus::new(state);
+        status.main_pid = service_status.process_id;
+        if state != crate::ServiceState::Running {
+            status.last_exit_code = Some(exit_code);
+        } else {
+            status.health = crate::health::load(&crate::health::health_dir(Scope::System),
+                                                &self.spec.name);
+        }
+        Ok(status)
+    }
+
+    fn logs(&self, _query: &LogQuery) -> crate::Result<Logs> {
+        Err(crate::Error::new(crate::ErrorKind::Unsupported,
+                              "Service logs are not available on Windows".to_string()))
+    }
 }
 
 #[cfg(test)]
 #[cfg(target_os = "windows")]
+// The tests kept from the first release predate these lints
+#[allow(non_fmt_panics, unused_must_use, clippy::unused_io_amount,
+        clippy::io_other_error)]
 mod tests {
     use super::*;
     use std::io::prelude::*;
@@ -256,4 +495,21 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn restart_delays_back_off() {
+        let policy = RestartPolicy::new(RestartMode::OnFailure)
+            .delay(Duration::from_secs(1))
+            .backoff(Duration::from_secs(8), 3);
+        let delays: Vec<_> = policy.delays().iter()
+            .map(|d| d.as_millis())
+            .collect();
+        assert_eq!(delays, vec![1000, 2000, 4000, 8000]);
+
+        let actions = SombraWindows::failure_actions(&policy.burst(2, Duration::from_secs(60)));
+        let actions = actions.actions.unwrap_or_default();
+        assert_eq!(actions.len(), 3);
+        assert_eq!(actions[1].delay, Duration::from_secs(2));
+        assert_eq!(actions[2].action_type, ServiceActionType::None);
+    }
 }
@@ -8,10 +8,8 @@ macro_rules! stdout {
     ($msg:expr) => {CommandOutput::Stdout($msg.to_string())};
 }
 
-fn run_cmd(cmd: &str, args: Vec<&str>) -> std::io::Result<CommandOutput> {
-    let output = std::process::Command::new(cmd)
-        .args(args)
-        .output()?;
+fn output(command: &mut std::process::Command) -> std::io::Result<CommandOutput> {
+    let output = command.output()?;
 
     let code = output.status.code().unwrap();
     if code == 0 {
@@ -23,16 +21,73 @@ fn run_cmd(cmd: &str, args: Vec<&str>) -> std::io::Result<CommandOutput> {
     }
 }
 
+#[cfg(target_os = "windows")]
+fn run_cmd(cmd: &str, args: Vec<&str>) -> std::io::Result<CommandOutput> {
+    output(std::process::Command::new(cmd).args(args))
+}
+
+// Unit directory of its own and the scripted systemctl, removed even if the test panics
+#[cfg(target_os = "linux")]
+struct FakeSystemd {
+    unit_dir: std::path::PathBuf,
+}
+
+#[cfg(target_os = "linux")]
+impl FakeSystemd {
+    fn new(test: &str) -> Self {
+        let unit_dir = std::env::temp_dir()
+            .join(format!("sombra-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&unit_dir);
+        std::fs::create_dir_all(&unit_dir).unwrap();
+        FakeSystemd { unit_dir }
+    }
+
+    fn run(&self, args: Vec<&str>) -> std::io::Result<CommandOutput> {
+        output(std::process::Command::new(env!("CARGO_BIN_EXE_sombra"))
+            .args(args)
+            .env("SOMBRA_BACKEND", "systemd")
+            .env("SOMBRA_SYSTEMCTL", concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fake_systemctl.sh"))
+            .env("SOMBRA_UNIT_DIR", &self.unit_dir))
+    }
+
+    // Arguments of every systemctl invocation so far
+    fn calls(&self) -> Vec<String> {
+        std::fs::read_to_string(self.unit_dir.join(".systemctl/calls"))
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for FakeSystemd {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.unit_dir);
+    }
+}
+
 #[test]
 #[cfg(target_os = "linux")]
 fn linux_normal_flow() -> std::io::Result<()> {
-    let res = run_cmd("./target/release/sombra", vec!["create", "tcp_echo",
-                                            "executables/tcp_echo"])?;
+    let systemd = FakeSystemd::new("normal_flow");
+    let res = systemd.run(vec!["create", "tcp_echo", "executables/tcp_echo"])?;
     assert_eq!(res, stdout!("[OK] Service tcp_echo created with success\n"));
+    assert!(systemd.unit_dir.join("tcp_echo.service").exists());
 
-    let res = run_cmd("./target/release/sombra", vec!["delete",
-                                                      "tcp_echo"])?;
+    let res = systemd.run(vec!["status", "tcp_echo"])?;
+    assert_eq!(res, stdout!("[OK] Service tcp_echo is running (pid 4242)\n"));
+
+    let res = systemd.run(vec!["delete", "tcp_echo"])?;
     assert_eq!(res, stdout!("[OK] Service tcp_echo deleted with success\n"));
+    assert!(!systemd.unit_dir.join("tcp_echo.service").exists());
+
+    assert_eq!(systemd.calls(), vec!["start tcp_echo", "is-active tcp_echo",
+                                     "show tcp_echo --property=LoadState,ActiveState,SubState,\
+                                      MainPID,ActiveEnterTimestampMonotonic,ExecMainCode,\
+                                      ExecMainStatus",
+                                     "stop tcp_echo", "disable tcp_echo", "daemon-reload",
+                                     "reset-failed"]);
 
     Ok(())
 }
@@ -40,22 +95,116 @@ fn linux_normal_flow() -> std::io::Result<()> {
 #[test]
 #[cfg(target_os = "linux")]
 fn linux_error_flow() -> std::io::Result<()>  {
-    run_cmd("./target/release/sombra", vec!["create", "tcp_echo", "executables/tcp_echo"])?;
+    let systemd = FakeSystemd::new("error_flow");
+    systemd.run(vec!["create", "tcp_echo", "executables/tcp_echo"])?;
 
-    let already_exist = run_cmd("./target/release/sombra", vec!["create", "tcp_echo", "executables/tcp_echo"])?;
+    let already_exist = systemd.run(vec!["create", "tcp_echo", "executables/tcp_echo"])?;
     assert_eq!(already_exist, stdout!("[ERR] <Io> Service tcp_echo already exist\n"));
 
-    let file_not_found = run_cmd("./target/release/sombra", vec!["create", "tcp_echo2", "executables/tcp_echos"])?;
+    let file_not_found = systemd.run(vec!["create", "tcp_echo2", "executables/tcp_echos"])?;
     assert_eq!(file_not_found, stdout!("[ERR] <Io> executables/tcp_echos: No such file or directory (os error 2)\n"));
 
-    run_cmd("./target/release/sombra", vec!["delete", "tcp_echo"])?;
+    systemd.run(vec!["delete", "tcp_echo"])?;
 
-    let service_not_find = run_cmd("./target/release/sombra", vec!["delete", "tcp_echo2"])?;
+    let deleted_twice = systemd.run(vec!["delete", "tcp_echo"])?;
+    assert_eq!(deleted_twice, stdout!("[ERR] <Io> No such file or directory (os error 2)\n"));
+
+    let service_not_find = systemd.run(vec!["delete", "tcp_echo2"])?;
     assert_eq!(service_not_find, stdout!("[ERR] <Io> No such file or directory (os error 2)\n"));
 
     Ok(())
 }
 
+// `sombra daemon` on a state directory of its own, stopped even if the test panics
+#[cfg(target_os = "linux")]
+struct Supervisor {
+    state_dir: std::path::PathBuf,
+    daemon: std::process::Child,
+}
+
+#[cfg(target_os = "linux")]
+impl Supervisor {
+    fn new(test: &str) -> Self {
+        let state_dir = std::env::temp_dir()
+            .join(format!("sombra-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&state_dir);
+        let daemon = Supervisor::command(&state_dir)
+            .arg("daemon")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let socket = state_dir.join("sombra.sock");
+        for _ in 0..100 {
+            if socket.exists() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        Supervisor { state_dir, daemon }
+    }
+
+    fn command(state_dir: &std::path::Path) -> std::process::Command {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_sombra"));
+        command.env("SOMBRA_BACKEND", "supervisor")
+            .env("SOMBRA_SUPERVISOR_DIR", state_dir);
+        command
+    }
+
+    fn run(&self, args: Vec<&str>) -> std::io::Result<CommandOutput> {
+        output(Supervisor::command(&self.state_dir).args(args))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        unsafe { libc::kill(self.daemon.id() as libc::pid_t, libc::SIGTERM) };
+        let _ = self.daemon.wait();
+        let _ = std::fs::remove_dir_all(&self.state_dir);
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn supervisor_flow() -> std::io::Result<()> {
+    let supervisor = Supervisor::new("supervisor_flow");
+    let res = supervisor.run(vec!["create", "greeter", "/bin/sh", "-c",
+                                  "echo hello; exec sleep 60"])?;
+    assert_eq!(res, stdout!("[OK] Service greeter created with success\n"));
+
+    match supervisor.run(vec!["status", "greeter"])? {
+        CommandOutput::Stdout(msg) => assert!(msg.starts_with("[OK] Service greeter is running (pid ")),
+        res => panic!("{:?}", res),
+    }
+    // The executable is canonicalized, /bin/sh may be a link
+    match supervisor.run(vec!["list"])? {
+        CommandOutput::Stdout(msg) => assert!(msg.starts_with("[OK] 1 service(s) created by \
+                                                               sombra\ngreeter\trunning\t")),
+        res => panic!("{:?}", res),
+    }
+
+    let res = supervisor.run(vec!["stop", "greeter"])?;
+    assert_eq!(res, stdout!("[OK] Service greeter stopped with success\n"));
+    let res = supervisor.run(vec!["status", "greeter"])?;
+    assert_eq!(res, CommandOutput::Stderr(String::new(), 3));
+
+    let res = supervisor.run(vec!["start", "greeter"])?;
+    assert_eq!(res, stdout!("[OK] Service greeter started with success\n"));
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let res = supervisor.run(vec!["logs", "greeter"])?;
+    assert_eq!(res, stdout!("hello\nhello\n[OK] 2 line(s) logged by service greeter\n"));
+
+    let res = supervisor.run(vec!["delete", "greeter"])?;
+    assert_eq!(res, stdout!("[OK] Service greeter deleted with success\n"));
+    assert!(!supervisor.state_dir.join("services/greeter.service").exists());
+
+    let res = supervisor.run(vec!["create", "failing", "/bin/false"])?;
+    assert_eq!(res, stdout!("[ERR] <ServiceManager> sombra daemon status failing: Service \
+                             failing is failed after start, expected running\n"));
+
+    Ok(())
+}
+
 #[test]
 #[cfg(target_os = "windows")]
 fn windows_normal_flow() -> std::io::Result<()> {
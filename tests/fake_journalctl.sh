@@ -0,0 +1,39 @@
+#!/bin/sh
+# Stand-in for journalctl in the library tests, called through a link next to the one of
+# fake_systemctl.sh. It prints the output fake_systemctl.sh collected for the unit, as
+# --output=cat or as --output=json entries, honoring --lines.
+set -u
+
+state="$(dirname "$0")/.systemctl"
+unit=
+output=short
+lines=
+
+for arg in "$@"; do
+    case "$arg" in
+        --unit=*|--user-unit=*) unit="${arg#*=}" ;;
+        --output=*) output="${arg#*=}" ;;
+        --lines=*) lines="${arg#*=}" ;;
+    esac
+done
+log="$state/${unit%.service}.log"
+
+if [ ! -f "$log" ]; then
+    exit 0
+fi
+if [ -n "$lines" ]; then
+    messages=$(tail -n "$lines" "$log")
+else
+    messages=$(cat "$log")
+fi
+if [ -z "$messages" ]; then
+    exit 0
+fi
+
+if [ "$output" = json ]; then
+    now=$(date +%s%6N)
+    printf '%s\n' "$messages" | sed -e 's/\\/\\\\/g' -e 's/"/\\"/g' \
+        -e "s/.*/{\"__REALTIME_TIMESTAMP\":\"$now\",\"MESSAGE\":\"&\"}/"
+else
+    printf '%s\n' "$messages"
+fi
@@ -0,0 +1,177 @@
+#!/bin/sh
+# Stand-in for systemctl in the CLI tests, selected with SOMBRA_SYSTEMCTL. It appends every
+# invocation to $SOMBRA_UNIT_DIR/.systemctl/calls and keeps the state of each unit in files
+# next to it, so tests run without root, without systemd and in parallel.
+#
+# Called through a link, as the library tests do, the directory of the link is the unit
+# directory and start really runs the ExecStart= of the unit, in a session of its own so stop
+# can kill it whole. Restart=always is honored and the output goes to <unit>.log, which
+# fake_journalctl.sh reads.
+set -u
+
+case "$0" in
+    */fake_systemctl.sh)
+        dir="${SOMBRA_UNIT_DIR:?SOMBRA_UNIT_DIR is not set}"
+        run=
+        ;;
+    *)
+        dir=$(cd "$(dirname "$0")" && pwd)
+        run=yes
+        ;;
+esac
+state="$dir/.systemctl"
+mkdir -p "$state"
+
+if [ "${1:-}" = "--user" ]; then
+    shift
+fi
+command="${1:-}"
+unit="${2:-}"
+unit="${unit%.service}"
+unit_file="$dir/$unit.service"
+
+# Value of a directive of the [Service] section, the marker section repeats some of them
+directive() {
+    sed -n "/^\[Service\]/,/^\[/s/^$1=//p" "$unit_file"
+}
+
+# Session leader started by launch, runs the service until it exits for good
+if [ "$command" = __run ]; then
+    exec_start=$(directive ExecStart)
+    directory=$(directive WorkingDirectory)
+    restart=$(directive Restart)
+    delay=$(directive RestartSec)
+    delay="${delay:-100ms}"
+    eval "set -- $exec_start"
+    cd "${directory:-/}" || exit 1
+    # Stopped on purpose, the exit status of the service is not a failure
+    trap 'exit 0' TERM
+    while :; do
+        "$@" >> "$state/$unit.log" 2>&1
+        echo $? > "$state/$unit.status"
+        [ "$restart" = always ] || exit 0
+        sleep "$(awk "BEGIN { print ${delay%ms} / 1000 }")"
+    done
+fi
+
+echo "$*" >> "$state/calls"
+
+exists() {
+    [ -f "$unit_file" ]
+}
+
+not_found() {
+    echo "Unit $unit.service not found." >&2
+    exit 5
+}
+
+main_pid() {
+    cat "$state/$unit.pid" 2>/dev/null
+}
+
+# Nothing reaps the session leader once it exits, a zombie counts as gone
+running() {
+    pid=$(main_pid) || return 1
+    [ -f "/proc/$pid/stat" ] && ! grep -q '^[^)]*) Z' "/proc/$pid/stat"
+}
+
+log_size() {
+    cat "$state/$unit.log" 2>/dev/null | wc -c
+}
+
+# Returns once the service printed something or exited, as tcp_echo prints its address once
+# it listens, so the tests need not wait for the port
+launch() {
+    running && return
+    rm -f "$state/$unit.status"
+    size=$(log_size)
+    setsid "$0" __run "$unit" < /dev/null > /dev/null 2>&1 &
+    echo $! > "$state/$unit.pid"
+    tries=0
+    while [ "$(log_size)" = "$size" ] && [ ! -f "$state/$unit.status" ] && [ $tries -lt 100 ]; do
+        sleep 0.01
+        tries=$((tries + 1))
+    done
+}
+
+kill_service() {
+    if running; then
+        kill -TERM "-$(main_pid)" 2>/dev/null
+        tries=0
+        while running && [ $tries -lt 100 ]; do
+            sleep 0.05
+            tries=$((tries + 1))
+        done
+    fi
+    rm -f "$state/$unit.pid" "$state/$unit.status"
+}
+
+active_state() {
+    if [ -z "$run" ]; then
+        cat "$state/$unit.active" 2>/dev/null || echo inactive
+    elif running; then
+        echo active
+    elif [ "$(cat "$state/$unit.status" 2>/dev/null || echo 0)" != 0 ]; then
+        echo failed
+    else
+        echo inactive
+    fi
+}
+
+case "$command" in
+    start|restart)
+        exists || not_found
+        if [ -n "$run" ]; then
+            [ "$command" = restart ] && kill_service
+            launch
+        fi
+        echo active > "$state/$unit.active"
+        ;;
+    stop)
+        exists || not_found
+        [ -n "$run" ] && kill_service
+        echo inactive > "$state/$unit.active"
+        ;;
+    is-active)
+        active=$(active_state)
+        echo "$active"
+        [ "$active" = active ]
+        ;;
+    show)
+        if exists; then
+            echo LoadState=loaded
+        else
+            echo LoadState=not-found
+        fi
+        active=$(active_state)
+        echo "ActiveState=$active"
+        if [ "$active" = active ]; then
+            echo SubState=running
+            if [ -n "$run" ]; then
+                echo "MainPID=$(main_pid)"
+            else
+                echo MainPID=4242
+            fi
+        else
+            echo SubState=dead
+            echo MainPID=0
+        fi
+        if [ -f "$state/$unit.status" ]; then
+            echo ExecMainCode=1
+            echo "ExecMainStatus=$(cat "$state/$unit.status")"
+        fi
+        ;;
+    enable)
+        exists || not_found
+        touch "$state/$unit.enabled"
+        ;;
+    disable)
+        rm -f "$state/$unit.enabled"
+        ;;
+    daemon-reload|reset-failed)
+        ;;
+    *)
+        echo "Unknown command verb $command." >&2
+        exit 1
+        ;;
+esac